@@ -1 +1,87 @@
-pub struct AppState;
+use std::sync::Arc;
+
+use crate::services::auth::IdentityProvider;
+use crate::services::jobs::JobQueue;
+use crate::services::quota::UsageTracker;
+use crate::services::retention::RetentionPolicy;
+use crate::services::share_links::ShareLinks;
+use crate::services::storage::Storage;
+use crate::services::tts_limiter::TtsLimiter;
+
+/// Shared, per-application state handed to every handler via `web::Data`.
+///
+/// Built once in `main` and cloned into every Actix worker, so the job table
+/// is the same no matter which worker serves a request.
+pub struct AppState {
+    pub jobs: JobQueue,
+    /// Where generated files are stored; also used by the job workers.
+    pub storage: Arc<dyn Storage>,
+    /// Verifies session tokens for the `authenticate` middleware.
+    pub auth: Arc<dyn IdentityProvider>,
+    /// Signs and checks public share links, and counts their downloads.
+    pub share_links: ShareLinks,
+    /// What the background cleanup removes; reported by `/api/cleanup/report`.
+    pub retention: RetentionPolicy,
+    /// Per-user storage and audio limits checked before queueing jobs.
+    pub quota: UsageTracker,
+    /// Concurrency and rate limits shared by every TTS call the job
+    /// workers make.
+    pub tts_limiter: Arc<TtsLimiter>,
+    /// The one configured outbound HTTP client. The TTS provider and S3
+    /// storage hold clones of it; identity providers are handed it per call.
+    pub http: reqwest::Client,
+}
+
+/// State backed by offline services: mock TTS, local storage under `root`
+/// and HS256 tokens signed with `test_secret`.
+#[cfg(test)]
+pub fn test_state(root: &std::path::Path, test_secret: &[u8]) -> actix_web::web::Data<AppState> {
+    use crate::services::auth::HmacJwt;
+    use crate::services::generation::GenerationContext;
+    use crate::services::local_storage::LocalStorage;
+    use crate::services::mock_tts::MockTts;
+    use crate::services::quota::QuotaLimits;
+    use crate::services::tts_limiter::{LimitedTts, TtsLimits};
+
+    let storage: Arc<dyn Storage> = Arc::new(LocalStorage::new(root).unwrap());
+    let tts_limiter = Arc::new(TtsLimiter::new(TtsLimits::default()));
+    let ctx = GenerationContext {
+        tts: Arc::new(LimitedTts::new(
+            Arc::new(MockTts::new()),
+            tts_limiter.clone(),
+        )),
+        storage: storage.clone(),
+    };
+    actix_web::web::Data::new(AppState {
+        jobs: JobQueue::start(1, ctx),
+        storage,
+        auth: Arc::new(HmacJwt::new(test_secret)),
+        share_links: ShareLinks::new(test_secret),
+        retention: RetentionPolicy::default(),
+        quota: UsageTracker::new(QuotaLimits::default()),
+        tts_limiter,
+        http: reqwest::Client::new(),
+    })
+}
+
+/// An HS256 session token for `sub`, valid for an hour, that [`test_state`]
+/// accepts when signed with the same `secret`.
+#[cfg(test)]
+pub fn test_token(secret: &[u8], sub: &str) -> String {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use ring::hmac;
+    use serde_json::json;
+
+    let encode = |v: serde_json::Value| URL_SAFE_NO_PAD.encode(v.to_string());
+    let exp = chrono::Utc::now().timestamp() + 3600;
+    let signed = format!(
+        "{}.{}",
+        encode(json!({"alg": "HS256"})),
+        encode(json!({"sub": sub, "exp": exp}))
+    );
+    let tag = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, secret),
+        signed.as_bytes(),
+    );
+    format!("{signed}.{}", URL_SAFE_NO_PAD.encode(tag.as_ref()))
+}
@@ -0,0 +1,36 @@
+use actix_web::{
+    get,
+    web::{Data, ServiceConfig},
+    HttpResponse,
+};
+use chrono::Utc;
+
+use crate::app_state::AppState;
+use crate::error::AppError;
+use crate::services::auth::Identity;
+use crate::services::retention::cleanup;
+
+/// GET /cleanup/report
+/// Dry run of the retention policy over the caller's files: which runs and
+/// chunk files the next cleanup pass will delete, and how many bytes that
+/// frees. Nothing is deleted.
+#[get("/cleanup/report")]
+async fn cleanup_report(
+    state: Data<AppState>,
+    identity: Identity,
+) -> Result<HttpResponse, AppError> {
+    let prefix = format!("{}/", identity.user_id);
+    let report = cleanup(
+        state.storage.as_ref(),
+        &state.retention,
+        &prefix,
+        true,
+        Utc::now(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(cleanup_report);
+}
@@ -1,137 +1,1023 @@
-use actix_web::{
-    get,
-    web::{self, ServiceConfig},
-    HttpResponse, Responder,
+use crate::app_state::AppState;
+use crate::endpoints::streaming::stream_object;
+use crate::error::AppError;
+use crate::services::auth::Identity;
+use crate::services::generation::{content_type_for, create_work_dir};
+use crate::services::manifest::{Manifest, MANIFEST_FILE};
+use crate::services::share_links::{is_shareable, ShareGrant};
+use crate::services::storage::{
+    download_to_file, media_url, object_key, Storage, StorageError, StoragePath,
 };
-use chrono::NaiveDateTime;
+use crate::services::tts_service::AudioFormat;
+use crate::utils::audio_metadata::{mp3_metadata_from_prefix, AudioMetadata, PREFIX_LEN};
 use crate::utils::convert_to_mp4::convert_to_mp4;
-use serde::Serialize;
+use crate::utils::folder_names::{folder_created_at, LEGACY_FOLDER_FORMAT};
+use crate::utils::storage_path::validate_segment;
+use crate::utils::ulid::Ulid;
+use actix_web::{
+    delete, get, patch, post,
+    web::{self, Data, ServiceConfig},
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Local, Utc};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tokio::task;
+
+/// How long presigned `file_path` URLs in a listing stay valid (S3 only;
+/// local files are served by `get_media`).
+const LISTING_URL_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Share link lifetime when the request doesn't give one, and the longest
+/// allowed.
+const DEFAULT_SHARE_TTL_SECS: i64 = 24 * 60 * 60;
+const MAX_SHARE_TTL_SECS: i64 = 7 * 24 * 60 * 60;
 
+/// Longest title accepted by `PATCH /files/{dir_name}`, in characters.
+const MAX_TITLE_CHARS: usize = 200;
+/// Most folders one bulk delete may name.
+const MAX_BULK_DELETE: usize = 100;
 
-/// Holds data for each discovered `final.mp3`.
+/// Check a client-supplied folder or file name before building keys from it.
+fn checked_dir_name(name: &str) -> Result<(), AppError> {
+    validate_segment(name).map_err(|e| StorageError::from(e).into())
+}
+
+/// Holds data for each discovered `final.<ext>` audio file.
 #[derive(Debug)]
 struct FinalFile {
-    timestamp: NaiveDateTime,
-    file_path: String,
+    timestamp: DateTime<Utc>,
+    key: StoragePath,
     dir_name: String,
+    file_name: String,
+    format: AudioFormat,
+    size: u64,
 }
 
 /// Response struct so we can serialize the timestamp back to a string.
+/// Audio fields (`duration_secs`, `size_bytes`, ...) are omitted when the
+/// manifest doesn't record them and the file can't be parsed as MP3.
 #[derive(Serialize)]
 struct FinalFileResponse {
+    /// Local time, minute resolution, as older clients expect.
     timestamp: String,
+    created_at: DateTime<Utc>,
     file_path: String,
     dir_name: String,
+    /// `final.<ext>`, in the format the audio was requested in.
+    file_name: String,
+    content_type: &'static str,
+    title: Option<String>,
+    #[serde(flatten)]
+    audio: Option<AudioMetadata>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum SortOrder {
+    /// Oldest first.
+    #[default]
+    Asc,
+    Desc,
 }
 
+/// Query string of `GET /files`. Everything is optional.
+#[derive(Deserialize)]
+struct ListQuery {
+    #[serde(default)]
+    order: SortOrder,
+    /// Page size, `DEFAULT_PAGE_SIZE` if not given and at most
+    /// `MAX_PAGE_SIZE`.
+    limit: Option<usize>,
+    /// `X-Next-Cursor` of the previous page.
+    cursor: Option<String>,
+    /// Only folders created at or after this time.
+    from: Option<DateTime<Utc>>,
+    /// Only folders created before this time.
+    to: Option<DateTime<Utc>>,
+    /// Case-insensitive text to look for in the input text or title.
+    q: Option<String>,
+}
+
+/// Page size of `GET /files` when the query doesn't set `limit`, and the
+/// largest one it accepts.
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+
+/// Response header carrying the cursor of the next page.
+const NEXT_CURSOR_HEADER: &str = "X-Next-Cursor";
+
 /// GET /speech/files
-/// Returns all final.mp3 files for the current user, sorted by the timestamp
-/// embedded in the folder name (e.g. "2025-04-03-14:03").
+/// Returns the final audio files of the current user, sorted by the creation
+/// time embedded in the folder name: a ULID, or a legacy "2025-04-03-14:03".
+/// Supports `order=asc|desc`, `from`/`to` (RFC 3339), a text search `q`, and
+/// cursor pagination: pages hold `limit` folders, and a `X-Next-Cursor`
+/// header is set while more may remain; pass it back as `cursor` for the
+/// next page.
 #[get("/files")]
-async fn list_speech_files() -> impl Responder {
-    // 1) Build path: user_files/<user_id>
-    let user_id = "public";
-    let user_dir_path = format!("user_files/{}", user_id);
-
-    // 3) Read top-level directory for this user
-    let read_dir = match fs::read_dir(&user_dir_path) {
-        Ok(d) => d,
-        Err(_) => {
-            // If directory doesn't exist or can't be read, return an empty JSON array
-            let empty: Vec<FinalFileResponse> = Vec::new();
-            return HttpResponse::Ok().json(empty);
+async fn list_speech_files(
+    state: Data<AppState>,
+    identity: Identity,
+    query: web::Query<ListQuery>,
+) -> Result<HttpResponse, AppError> {
+    let query = query.into_inner();
+    if query.limit.is_some_and(|l| l == 0 || l > MAX_PAGE_SIZE) {
+        return Err(AppError::InvalidInput(format!(
+            "limit must be between 1 and {MAX_PAGE_SIZE}"
+        )));
+    }
+    let cursor = match query.cursor.as_deref().map(|c| (folder_created_at(c), c)) {
+        None => None,
+        Some((Some(timestamp), dir_name)) => Some((timestamp, dir_name.to_string())),
+        Some((None, _)) => return Err(AppError::InvalidInput("invalid cursor".to_string())),
+    };
+    let search = query
+        .q
+        .as_deref()
+        .map(|q| q.trim().to_lowercase())
+        .filter(|q| !q.is_empty());
+
+    // 1) List what is stored under <user_id>/. Going forward from a ULID
+    //    cursor, the backend can skip everything up to it: ULIDs sort by
+    //    creation time, and legacy names ("2025-...") sort after all of them.
+    let user_id = identity.user_id.as_str();
+    let prefix = format!("{user_id}/");
+    let objects = match &cursor {
+        Some((_, dir_name))
+            if query.order == SortOrder::Asc && dir_name.parse::<Ulid>().is_ok() =>
+        {
+            let start_after = format!("{prefix}{dir_name}");
+            state.storage.list_after(&prefix, &start_after).await?
         }
+        _ => state.storage.list(&prefix).await?,
     };
 
+    // 2) Pick out each folder's final audio and note which have a manifest
     let mut final_files: Vec<FinalFile> = Vec::new();
-
-    for entry in read_dir.flatten() {
-        // Each entry is expected to be a timestamp-based folder, e.g. "2025-04-03-14:03"
-        let meta = match entry.metadata() {
-            Ok(m) => m,
-            Err(_) => continue, // skip unreadable entries
+    let mut manifests = HashSet::new();
+    for object in &objects {
+        let Some((dir_name, file_name)) = object
+            .key
+            .as_str()
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.split_once('/'))
+        else {
+            continue;
         };
-        if meta.is_dir() {
-            let dir_name = match entry.file_name().into_string() {
-                Ok(s) => s,
-                Err(_) => continue, // skip non-UTF8
-            };
-
-            // 4) Construct path to final.mp3
-            let final_mp3_path = format!("{}/{}/final.mp3", user_dir_path, dir_name);
-            if fs::metadata(&final_mp3_path).is_err() {
-                // If final.mp3 doesn't exist, skip
-                continue;
-            }
-
-            // 5) Parse the directory name as a NaiveDateTime
-            let maybe_timestamp = NaiveDateTime::parse_from_str(&dir_name, "%Y-%m-%d-%H:%M");
-            if let Ok(dt) = maybe_timestamp {
+        if file_name == MANIFEST_FILE {
+            manifests.insert(dir_name.to_string());
+        } else if let Some(format) = file_name
+            .strip_prefix("final.")
+            .and_then(|ext| ext.parse::<AudioFormat>().ok())
+        {
+            // 3) Recover the creation time from the directory name
+            if let Some(dt) = folder_created_at(dir_name) {
                 final_files.push(FinalFile {
                     timestamp: dt,
-                    file_path: final_mp3_path,
-                    dir_name,
+                    key: object.key.clone(),
+                    dir_name: dir_name.to_string(),
+                    file_name: file_name.to_string(),
+                    format,
+                    size: object.size,
                 });
             }
         }
     }
 
-    // 6) Sort by timestamp ascending (oldest first).
-    //    For newest first, use `final_files.sort_by_key(|f| std::cmp::Reverse(f.timestamp));`
-    final_files.sort_by_key(|f| f.timestamp);
+    // 4) Apply the date range and cursor, then sort by timestamp and then
+    //    name, so ULIDs from the same millisecond keep their creation order.
+    final_files.retain(|file| {
+        let position = (file.timestamp, &file.dir_name);
+        query.from.is_none_or(|from| file.timestamp >= from)
+            && query.to.is_none_or(|to| file.timestamp < to)
+            && cursor
+                .as_ref()
+                .is_none_or(|(timestamp, dir_name)| match query.order {
+                    SortOrder::Asc => position > (*timestamp, dir_name),
+                    SortOrder::Desc => position < (*timestamp, dir_name),
+                })
+    });
+    final_files.sort_by(|a, b| (a.timestamp, &a.dir_name).cmp(&(b.timestamp, &b.dir_name)));
+    if query.order == SortOrder::Desc {
+        final_files.reverse();
+    }
+
+    // 5) Build response with a fetchable URL, human-readable date/time and
+    //    audio metadata, stopping before any more manifests are read once
+    //    the page is full
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let mut result: Vec<FinalFileResponse> = Vec::new();
+    let mut has_more = false;
+    for file in final_files {
+        if result.len() == limit {
+            has_more = true;
+            break;
+        }
+        let manifest = if manifests.contains(&file.dir_name) {
+            load_manifest(state.storage.as_ref(), user_id, &file.dir_name).await
+        } else {
+            None
+        };
+        if let Some(search) = &search {
+            if !manifest.as_ref().is_some_and(|m| matches_search(m, search)) {
+                continue;
+            }
+        }
+
+        let file_path = media_url(
+            state.storage.as_ref(),
+            user_id,
+            &file.dir_name,
+            &file.file_name,
+            LISTING_URL_TTL,
+        )
+        .await?;
+        let (title, audio) = folder_details(state.storage.as_ref(), &file, manifest).await;
+        result.push(FinalFileResponse {
+            timestamp: file
+                .timestamp
+                .with_timezone(&Local)
+                .format(LEGACY_FOLDER_FORMAT)
+                .to_string(),
+            created_at: file.timestamp,
+            file_path,
+            dir_name: file.dir_name,
+            file_name: file.file_name,
+            content_type: file.format.content_type(),
+            title,
+            audio,
+        });
+    }
+
+    let mut response = HttpResponse::Ok();
+    if has_more {
+        if let Some(last) = result.last() {
+            response.insert_header((NEXT_CURSOR_HEADER, last.dir_name.clone()));
+        }
+    }
+    Ok(response.json(result))
+}
+
+async fn load_manifest(storage: &dyn Storage, user_id: &str, dir_name: &str) -> Option<Manifest> {
+    let key = object_key(user_id, dir_name, MANIFEST_FILE).ok()?;
+    let bytes = storage.get(&key).await.ok()?;
+    Manifest::from_json(&bytes).ok()
+}
+
+/// Whether the input text or title contains `search`, which must already be
+/// lower-case.
+fn matches_search(manifest: &Manifest, search: &str) -> bool {
+    manifest.request.text.to_lowercase().contains(search)
+        || manifest
+            .title
+            .as_ref()
+            .is_some_and(|t| t.to_lowercase().contains(search))
+}
+
+/// Title and audio metadata recorded in the folder's manifest. Folders from
+/// before manifests carried audio metadata have the head of their MP3
+/// fetched and scanned instead.
+async fn folder_details(
+    storage: &dyn Storage,
+    file: &FinalFile,
+    manifest: Option<Manifest>,
+) -> (Option<String>, Option<AudioMetadata>) {
+    let title = manifest.as_ref().and_then(|m| m.title.clone());
+    if let Some(mut audio) = manifest.and_then(|m| m.audio) {
+        audio.size_bytes = file.size;
+        return (title, Some(audio));
+    }
+    // Older folders have no metadata in their manifest; read it from the
+    // head of the file rather than downloading the whole recording
+    if file.size == 0 || file.format != AudioFormat::Mp3 {
+        return (title, None);
+    }
+    let prefix = match storage
+        .stream(&file.key, Some(0..file.size.min(PREFIX_LEN)))
+        .await
+    {
+        Ok(body) => body.try_concat().await.ok(),
+        Err(_) => None,
+    };
+    let audio = prefix.and_then(|head| mp3_metadata_from_prefix(&head, file.size));
+    (title, audio)
+}
 
-    // 7) Build response with human-readable date/time
-    let result: Vec<FinalFileResponse> = final_files
-        .iter()
-        .map(|file| FinalFileResponse {
-            timestamp: file.timestamp.format("%Y-%m-%d-%H:%M").to_string(),
-            file_path: file.file_path.clone(),
-            dir_name: file.dir_name.clone(),
-        })
-        .collect();
+/// GET /files/{dir_name}
+/// Returns the `manifest.json` recorded when the folder was generated:
+/// the original request, chunk boundaries, timings and any errors.
+#[get("/files/{dir_name}")]
+async fn file_manifest(
+    state: Data<AppState>,
+    identity: Identity,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let user_id = identity.user_id.as_str();
+    let dir_name = path.into_inner();
+    checked_dir_name(&dir_name)?;
 
-    HttpResponse::Ok().json(result)
+    let bytes = state
+        .storage
+        .get(&object_key(user_id, &dir_name, MANIFEST_FILE)?)
+        .await?;
+    let manifest = Manifest::from_json(&bytes).map_err(AppError::Internal)?;
+    Ok(HttpResponse::Ok().json(manifest))
 }
 
 /// GET /files/{dir_name}/mp4
 /// Converts `final.mp3` to `final.mp4` inside the specified directory and
-/// returns the MP4 bytes. If the MP4 already exists it is reused.
+/// streams the MP4. If the MP4 already exists it is reused.
 #[get("/files/{dir_name}/mp4")]
 async fn mp4_for_file(
+    state: Data<AppState>,
+    identity: Identity,
+    req: HttpRequest,
     path: web::Path<String>,
-) -> impl Responder {
-    let user_id = "public";
+) -> Result<HttpResponse, AppError> {
+    let user_id = identity.user_id.as_str();
     let dir_name = path.into_inner();
-    // Build paths under user_files
-    let folder_path = format!("user_files/{}/{}", user_id, dir_name);
-    let final_mp3_path = format!("{}/final.mp3", folder_path);
-    let final_mp4_path = format!("{}/final.mp4", folder_path);
+    checked_dir_name(&dir_name)?;
+    let final_mp3_key = object_key(user_id, &dir_name, "final.mp3")?;
+    let final_mp4_key = object_key(user_id, &dir_name, "final.mp4")?;
 
-    if fs::metadata(&final_mp3_path).is_err() {
-        return HttpResponse::NotFound()
-            .json(serde_json::json!({ "error": "final.mp3 not found" }));
+    // 1) Reuse an MP4 converted earlier
+    match state.storage.head(&final_mp4_key).await {
+        Ok(_) => {
+            return stream_object(&req, state.storage.as_ref(), &final_mp4_key, "video/mp4").await
+        }
+        Err(StorageError::NotFound) => {}
+        Err(e) => return Err(e.into()),
     }
 
-    if fs::metadata(&final_mp4_path).is_err() {
-        if let Err(e) = convert_to_mp4(&final_mp3_path, &final_mp4_path) {
-            return HttpResponse::InternalServerError().json(serde_json::json!({ "error": e }));
+    // 2) Download final.mp3 into a scratch directory, convert it there off
+    //    the async runtime and store the result straight from disk
+    let work_dir = create_work_dir(&format!("{dir_name}-mp4-{}", Ulid::new()))?;
+    let final_mp3_path = format!("{work_dir}/final.mp3");
+    let final_mp4_path = format!("{work_dir}/final.mp4");
+    let converted = async {
+        download_to_file(
+            state.storage.as_ref(),
+            &final_mp3_key,
+            Path::new(&final_mp3_path),
+        )
+        .await
+        .map_err(|e| match e {
+            StorageError::NotFound => AppError::NotFound("final.mp3 not found".to_string()),
+            e => e.into(),
+        })?;
+        let (input, output) = (final_mp3_path.clone(), final_mp4_path.clone());
+        task::spawn_blocking(move || convert_to_mp4(&input, &output))
+            .await
+            .map_err(|e| AppError::Internal(format!("Join error on ffmpeg task: {e}")))??;
+        state
+            .storage
+            .put_file(&final_mp4_key, Path::new(&final_mp4_path), "video/mp4")
+            .await?;
+        Ok::<_, AppError>(())
+    }
+    .await;
+    if let Err(e) = fs::remove_dir_all(&work_dir) {
+        tracing::error!("Failed to remove {}: {:?}", work_dir, e);
+    }
+    converted?;
+
+    // 3) Serve it from storage like later requests will
+    stream_object(&req, state.storage.as_ref(), &final_mp4_key, "video/mp4").await
+}
+
+/// GET /media/{dir_name}/{file_name}
+/// Streams a file from one of the caller's own output folders, with range
+/// and conditional request support. Keys are built from the caller's user
+/// id, so other users' files can't be reached.
+#[get("/media/{dir_name}/{file_name}")]
+async fn get_media(
+    state: Data<AppState>,
+    identity: Identity,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, AppError> {
+    let (dir_name, file_name) = path.into_inner();
+    checked_dir_name(&dir_name)?;
+    checked_dir_name(&file_name)?;
+
+    let key = object_key(&identity.user_id, &dir_name, &file_name)?;
+    stream_object(
+        &req,
+        state.storage.as_ref(),
+        &key,
+        content_type_for(&file_name),
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+struct ShareRequest {
+    /// `final.mp4` or `final.<ext>` for an audio format.
+    file: String,
+    expires_in_secs: Option<i64>,
+    max_downloads: Option<u32>,
+}
+
+/// POST /files/{dir_name}/share
+/// Mints a signed link to the folder's final audio or `final.mp4` that
+/// anyone can open without signing in until it expires or runs out of
+/// downloads.
+#[post("/files/{dir_name}/share")]
+async fn create_share_link(
+    state: Data<AppState>,
+    identity: Identity,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<ShareRequest>,
+) -> Result<HttpResponse, AppError> {
+    let dir_name = path.into_inner();
+    checked_dir_name(&dir_name)?;
+
+    // 1) Validate the request
+    let ShareRequest {
+        file,
+        expires_in_secs,
+        max_downloads,
+    } = body.into_inner();
+    if !is_shareable(&file) {
+        return Err(AppError::InvalidInput(
+            "file must be final.mp4 or the folder's final audio file".to_string(),
+        ));
+    }
+    let expires_in_secs = expires_in_secs.unwrap_or(DEFAULT_SHARE_TTL_SECS);
+    if !(1..=MAX_SHARE_TTL_SECS).contains(&expires_in_secs) {
+        return Err(AppError::InvalidInput(format!(
+            "expires_in_secs must be between 1 and {MAX_SHARE_TTL_SECS}"
+        )));
+    }
+    if max_downloads == Some(0) {
+        return Err(AppError::InvalidInput(
+            "max_downloads must be at least 1".to_string(),
+        ));
+    }
+
+    // 2) Only share files that exist and belong to the caller
+    let key = object_key(&identity.user_id, &dir_name, &file)?;
+    state.storage.head(&key).await.map_err(|e| match e {
+        StorageError::NotFound => AppError::NotFound(format!("{file} not found")),
+        e => e.into(),
+    })?;
+
+    // 3) Sign the grant
+    let grant = ShareGrant {
+        id: Ulid::new().to_string(),
+        user_id: identity.user_id,
+        dir_name,
+        file_name: file,
+        expires_at: Utc::now() + chrono::Duration::seconds(expires_in_secs),
+        max_downloads,
+    };
+    let token = state.share_links.mint(&grant);
+    let conn = req.connection_info();
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "url": format!("{}://{}/share/{}", conn.scheme(), conn.host(), token),
+        "expires_at": grant.expires_at,
+        "max_downloads": grant.max_downloads,
+    })))
+}
+
+/// Delete every object in one of `user_id`'s output folders and return how
+/// many there were. `NotFound` if the folder is empty or missing.
+async fn delete_folder(
+    storage: &dyn Storage,
+    user_id: &str,
+    dir_name: &str,
+) -> Result<usize, StorageError> {
+    let objects = storage.list(&format!("{user_id}/{dir_name}/")).await?;
+    if objects.is_empty() {
+        return Err(StorageError::NotFound);
+    }
+    for object in &objects {
+        match storage.delete(&object.key).await {
+            // Already gone, e.g. removed by a concurrent delete
+            Ok(()) | Err(StorageError::NotFound) => {}
+            Err(e) => return Err(e),
         }
     }
+    Ok(objects.len())
+}
+
+/// DELETE /files/{dir_name}
+/// Removes one of the caller's output folders and everything in it.
+#[delete("/files/{dir_name}")]
+async fn delete_file(
+    state: Data<AppState>,
+    identity: Identity,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let dir_name = path.into_inner();
+    checked_dir_name(&dir_name)?;
+
+    let count = delete_folder(state.storage.as_ref(), &identity.user_id, &dir_name).await?;
+    tracing::info!("Deleted {} ({} objects)", dir_name, count);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+struct FileUpdate {
+    /// New title; `null` or blank clears it.
+    title: Option<String>,
+}
+
+/// PATCH /files/{dir_name}
+/// Sets or clears the folder's title, stored in its `manifest.json`, and
+/// returns the updated manifest.
+#[patch("/files/{dir_name}")]
+async fn update_file(
+    state: Data<AppState>,
+    identity: Identity,
+    path: web::Path<String>,
+    body: web::Json<FileUpdate>,
+) -> Result<HttpResponse, AppError> {
+    let dir_name = path.into_inner();
+    checked_dir_name(&dir_name)?;
+    let title = body
+        .into_inner()
+        .title
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty());
+    if title
+        .as_ref()
+        .is_some_and(|t| t.chars().count() > MAX_TITLE_CHARS)
+    {
+        return Err(AppError::InvalidInput(format!(
+            "title must be at most {MAX_TITLE_CHARS} characters"
+        )));
+    }
 
-    let video_bytes = match fs::read(&final_mp4_path) {
+    // 1) Load the manifest; folders from before manifests can't hold a title
+    let key = object_key(&identity.user_id, &dir_name, MANIFEST_FILE)?;
+    let bytes = match state.storage.get(&key).await {
         Ok(bytes) => bytes,
-        Err(e) => {
-            return HttpResponse::InternalServerError()
-                .json(serde_json::json!({ "error": format!("Failed to read mp4: {e}") }));
+        Err(StorageError::NotFound) => {
+            let folder = format!("{}/{dir_name}/", identity.user_id);
+            if state.storage.list(&folder).await?.is_empty() {
+                return Err(StorageError::NotFound.into());
+            }
+            return Err(AppError::Conflict(
+                "folder has no manifest to store a title in".to_string(),
+            ));
         }
+        Err(e) => return Err(e.into()),
     };
+    let mut manifest = Manifest::from_json(&bytes).map_err(AppError::Internal)?;
 
-    HttpResponse::Ok()
-        .content_type("video/mp4")
-        .body(video_bytes)
+    // 2) Store it back with the new title
+    manifest.title = title;
+    let json = manifest.to_json().map_err(AppError::Internal)?;
+    state.storage.put(&key, json, "application/json").await?;
+    Ok(HttpResponse::Ok().json(manifest))
+}
+
+#[derive(Deserialize)]
+struct BulkDelete {
+    dir_names: Vec<String>,
+}
+
+/// POST /files/delete
+/// Deletes several of the caller's output folders. Every name is checked
+/// before anything is removed; the response lists which folders were
+/// deleted and which didn't exist.
+#[post("/files/delete")]
+async fn bulk_delete_files(
+    state: Data<AppState>,
+    identity: Identity,
+    body: web::Json<BulkDelete>,
+) -> Result<HttpResponse, AppError> {
+    let BulkDelete { mut dir_names } = body.into_inner();
+    if dir_names.len() > MAX_BULK_DELETE {
+        return Err(AppError::InvalidInput(format!(
+            "at most {MAX_BULK_DELETE} folders can be deleted at once"
+        )));
+    }
+    for dir_name in &dir_names {
+        checked_dir_name(dir_name)?;
+    }
+    dir_names.sort();
+    dir_names.dedup();
+
+    let mut deleted = Vec::new();
+    let mut not_found = Vec::new();
+    for dir_name in dir_names {
+        match delete_folder(state.storage.as_ref(), &identity.user_id, &dir_name).await {
+            Ok(_) => deleted.push(dir_name),
+            Err(StorageError::NotFound) => not_found.push(dir_name),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    tracing::info!("Bulk deleted {} folder(s)", deleted.len());
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "deleted": deleted,
+        "not_found": not_found,
+    })))
 }
 
 pub fn configure(cfg: &mut ServiceConfig) {
-    cfg.service(list_speech_files).service(mp4_for_file);
+    cfg.service(list_speech_files)
+        .service(file_manifest)
+        .service(mp4_for_file)
+        .service(get_media)
+        .service(create_share_link)
+        .service(delete_file)
+        .service(update_file)
+        .service(bulk_delete_files);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::{test_state, test_token};
+    use crate::middleware::auth::authenticate;
+    use crate::services::generation::SpeechRequest;
+    use crate::services::jobs::JobKind;
+    use crate::services::tts_service::TtsOptions;
+    use actix_web::{middleware::from_fn, test, App};
+    use std::path::PathBuf;
+
+    const SECRET: &[u8] = b"files-secret";
+
+    /// Fresh state over a scratch directory for the test `name`, with a
+    /// `final.mp3` in each of alice's `folders`.
+    async fn setup(name: &str, folders: &[&str]) -> (PathBuf, Data<AppState>) {
+        let dir = std::env::temp_dir().join(format!("files-{name}-{}", std::process::id()));
+        let state = test_state(&dir, SECRET);
+        for folder in folders {
+            state
+                .storage
+                .put(
+                    &object_key("alice", folder, "final.mp3").unwrap(),
+                    b"ID3".to_vec(),
+                    "audio/mpeg",
+                )
+                .await
+                .unwrap();
+        }
+        (dir, state)
+    }
+
+    /// Store a manifest for alice's `folder` with `text` as the input.
+    async fn put_manifest(state: &AppState, folder: &str, text: &str) {
+        let request = SpeechRequest {
+            user_id: "alice".to_string(),
+            text: text.to_string(),
+            voice: "alloy".to_string(),
+            options: TtsOptions::default(),
+        };
+        let manifest = Manifest::new(folder, JobKind::Speech, "mock", &request);
+        state
+            .storage
+            .put(
+                &object_key("alice", folder, MANIFEST_FILE).unwrap(),
+                manifest.to_json().unwrap(),
+                "application/json",
+            )
+            .await
+            .unwrap();
+    }
+
+    /// `req` with a session token for `user`.
+    fn as_user(user: &str, req: test::TestRequest) -> test::TestRequest {
+        req.insert_header((
+            "Authorization",
+            format!("Bearer {}", test_token(SECRET, user)),
+        ))
+    }
+
+    #[actix_web::test]
+    async fn media_is_only_served_to_its_owner() {
+        let folder = "01JR0000000000000000000000";
+        let (dir, state) = setup("media", &[folder]).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .wrap(from_fn(authenticate))
+                .configure(configure),
+        )
+        .await;
+        let get =
+            |user: &str, uri: &str| as_user(user, test::TestRequest::get().uri(uri)).to_request();
+        let uri = format!("/media/{folder}/final.mp3");
+
+        let own = test::call_service(&app, get("alice", &uri)).await;
+        assert_eq!(own.status(), 200);
+        assert_eq!(own.headers().get("content-type").unwrap(), "audio/mpeg");
+
+        let other = test::call_service(&app, get("bob", &uri)).await;
+        assert_eq!(other.status(), 404);
+        let other_video =
+            test::call_service(&app, get("bob", &format!("/files/{folder}/mp4"))).await;
+        assert_eq!(other_video.status(), 404);
+
+        let traversal = format!("/media/..%2Falice%2F{folder}/final.mp3");
+        let escaped = test::call_service(&app, get("bob", &traversal)).await;
+        assert_eq!(escaped.status(), 400);
+
+        let listing = test::call_and_read_body(&app, get("bob", "/files")).await;
+        assert_eq!(listing, "[]");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[actix_web::test]
+    async fn share_links_serve_without_a_session_until_used_up() {
+        let folder = "01JR0000000000000000000000";
+        let (dir, state) = setup("share", &[folder]).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .service(crate::endpoints::share::get_shared_file)
+                .service(
+                    web::scope("")
+                        .wrap(from_fn(authenticate))
+                        .configure(configure),
+                ),
+        )
+        .await;
+
+        let share = |file: &str, max_downloads: u32| {
+            let req = test::TestRequest::post()
+                .uri(&format!("/files/{folder}/share"))
+                .set_json(serde_json::json!({ "file": file, "max_downloads": max_downloads }));
+            as_user("alice", req).to_request()
+        };
+
+        let missing = test::call_service(&app, share("final.mp4", 1)).await;
+        assert_eq!(missing.status(), 404);
+        let not_shareable = test::call_service(&app, share("manifest.json", 1)).await;
+        assert_eq!(not_shareable.status(), 400);
+
+        let created: serde_json::Value =
+            test::call_and_read_body_json(&app, share("final.mp3", 1)).await;
+        let url = created["url"].as_str().unwrap();
+        let path = &url[url.find("/share/").unwrap()..];
+
+        let first = test::call_service(&app, test::TestRequest::get().uri(path).to_request()).await;
+        assert_eq!(first.status(), 200);
+        assert_eq!(first.headers().get("content-type").unwrap(), "audio/mpeg");
+        let second =
+            test::call_service(&app, test::TestRequest::get().uri(path).to_request()).await;
+        assert_eq!(second.status(), 410);
+
+        // A range not starting at the first byte still counts
+        let created: serde_json::Value =
+            test::call_and_read_body_json(&app, share("final.mp3", 1)).await;
+        let url = created["url"].as_str().unwrap();
+        let path = &url[url.find("/share/").unwrap()..];
+        let ranged = test::TestRequest::get()
+            .uri(path)
+            .insert_header(("range", "bytes=1-"))
+            .to_request();
+        let ranged = test::call_service(&app, ranged).await;
+        assert_eq!(ranged.status(), 206);
+        let full = test::call_service(&app, test::TestRequest::get().uri(path).to_request()).await;
+        assert_eq!(full.status(), 410);
+
+        let tampered = format!("{path}x");
+        let forged =
+            test::call_service(&app, test::TestRequest::get().uri(&tampered).to_request()).await;
+        assert_eq!(forged.status(), 403);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[actix_web::test]
+    async fn deletes_and_titles_folders() {
+        let (first, second, legacy) = (
+            "01JR0000000000000000000001",
+            "01JR0000000000000000000002",
+            "01JR0000000000000000000003",
+        );
+        let (dir, state) = setup("manage", &[first, second, legacy]).await;
+        for folder in [first, second] {
+            put_manifest(&state, folder, "Hi").await;
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .wrap(from_fn(authenticate))
+                .configure(configure),
+        )
+        .await;
+        let rename = |user: &str, folder: &str, title: serde_json::Value| {
+            let req = test::TestRequest::patch()
+                .uri(&format!("/files/{folder}"))
+                .set_json(serde_json::json!({ "title": title }));
+            as_user(user, req).to_request()
+        };
+
+        // Titles
+        let titled = test::call_service(&app, rename("alice", first, "  Intro ".into())).await;
+        assert_eq!(titled.status(), 200);
+        let listing: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            as_user("alice", test::TestRequest::get().uri("/files")).to_request(),
+        )
+        .await;
+        assert_eq!(listing[0]["title"], "Intro");
+        assert_eq!(listing[1]["title"], serde_json::Value::Null);
+        let no_manifest = test::call_service(&app, rename("alice", legacy, "x".into())).await;
+        assert_eq!(no_manifest.status(), 409);
+        let other_user = test::call_service(&app, rename("bob", first, "x".into())).await;
+        assert_eq!(other_user.status(), 404);
+
+        // Single delete
+        let delete = |user: &str| {
+            as_user(
+                user,
+                test::TestRequest::delete().uri(&format!("/files/{first}")),
+            )
+            .to_request()
+        };
+        assert_eq!(test::call_service(&app, delete("bob")).await.status(), 404);
+        assert_eq!(
+            test::call_service(&app, delete("alice")).await.status(),
+            204
+        );
+        assert_eq!(
+            test::call_service(&app, delete("alice")).await.status(),
+            404
+        );
+
+        // Bulk delete checks every name before deleting anything
+        let bulk = |names: &[&str]| {
+            let req = test::TestRequest::post()
+                .uri("/files/delete")
+                .set_json(serde_json::json!({ "dir_names": names }));
+            as_user("alice", req).to_request()
+        };
+        let invalid = test::call_service(&app, bulk(&[second, "..%2Fbob"])).await;
+        assert_eq!(invalid.status(), 400);
+        let result: serde_json::Value =
+            test::call_and_read_body_json(&app, bulk(&[second, legacy, first])).await;
+        assert_eq!(result["deleted"], serde_json::json!([second, legacy]));
+        assert_eq!(result["not_found"], serde_json::json!([first]));
+        assert!(is_empty_dir(&dir.join("alice")));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[actix_web::test]
+    async fn lists_and_shares_every_audio_format() {
+        let (mp3, wav) = ("01JR0000000000000000000001", "01JR0000000000000000000002");
+        let (dir, state) = setup("formats", &[mp3]).await;
+        state
+            .storage
+            .put(
+                &object_key("alice", wav, "final.wav").unwrap(),
+                vec![0; 100],
+                "audio/wav",
+            )
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .wrap(from_fn(authenticate))
+                .configure(configure),
+        )
+        .await;
+
+        let listing: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            as_user("alice", test::TestRequest::get().uri("/files")).to_request(),
+        )
+        .await;
+        assert_eq!(listing[0]["file_name"], "final.mp3");
+        assert_eq!(listing[0]["content_type"], "audio/mpeg");
+        assert_eq!(listing[1]["dir_name"], wav);
+        assert_eq!(listing[1]["file_name"], "final.wav");
+        assert_eq!(listing[1]["content_type"], "audio/wav");
+
+        let share = |file: &str| {
+            let req = test::TestRequest::post()
+                .uri(&format!("/files/{wav}/share"))
+                .set_json(serde_json::json!({ "file": file }));
+            as_user("alice", req).to_request()
+        };
+        assert_eq!(
+            test::call_service(&app, share("final.wav")).await.status(),
+            201
+        );
+        assert_eq!(
+            test::call_service(&app, share("final.mp3")).await.status(),
+            404
+        );
+        assert_eq!(
+            test::call_service(&app, share("final.txt")).await.status(),
+            400
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    fn is_empty_dir(dir: &std::path::Path) -> bool {
+        std::fs::read_dir(dir).map_or(true, |mut entries| entries.next().is_none())
+    }
+
+    #[actix_web::test]
+    async fn pages_filters_and_searches_the_listing() {
+        // One folder per day from 2025-01-01, each with a manifest
+        let day_ms = 24 * 60 * 60 * 1000;
+        let start_ms = 1_735_689_600_000;
+        let texts = [
+            "Hello world",
+            "Quarterly report",
+            "hello again",
+            "Recipe",
+            "Poem",
+        ];
+        let folders: Vec<String> = (0..texts.len())
+            .map(|i| Ulid::from_timestamp_ms(start_ms + i as u64 * day_ms).to_string())
+            .collect();
+        let folder_names: Vec<&str> = folders.iter().map(String::as_str).collect();
+        let (dir, state) = setup("list", &folder_names).await;
+        for (folder, text) in folders.iter().zip(texts) {
+            put_manifest(&state, folder, text).await;
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .wrap(from_fn(authenticate))
+                .configure(configure),
+        )
+        .await;
+        let list = |query: &str| {
+            as_user(
+                "alice",
+                test::TestRequest::get().uri(&format!("/files?{query}")),
+            )
+            .to_request()
+        };
+        let names = |body: &serde_json::Value| -> Vec<String> {
+            body.as_array()
+                .unwrap()
+                .iter()
+                .map(|f| f["dir_name"].as_str().unwrap().to_string())
+                .collect()
+        };
+        // Newest first, two at a time
+        let mut seen = Vec::new();
+        let mut query = "order=desc&limit=2".to_string();
+        loop {
+            let res = test::call_service(&app, list(&query)).await;
+            assert_eq!(res.status(), 200);
+            let cursor = res
+                .headers()
+                .get(NEXT_CURSOR_HEADER)
+                .map(|c| c.to_str().unwrap().to_string());
+            let body: serde_json::Value = test::read_body_json(res).await;
+            seen.extend(names(&body));
+            match cursor {
+                Some(cursor) => query = format!("order=desc&limit=2&cursor={cursor}"),
+                None => break,
+            }
+        }
+        let newest_first: Vec<_> = folders.iter().rev().cloned().collect();
+        assert_eq!(seen, newest_first);
+
+        // Oldest first picks up right after the cursor
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, list(&format!("limit=2&cursor={}", folders[1])))
+                .await;
+        assert_eq!(names(&body), &folders[2..4]);
+
+        // Date range: 2025-01-02 up to (not including) 2025-01-04
+        let body: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            list("from=2025-01-02T00:00:00Z&to=2025-01-04T00:00:00Z"),
+        )
+        .await;
+        assert_eq!(names(&body), &folders[1..3]);
+
+        // Search is case-insensitive and respects the page size
+        let body: serde_json::Value = test::call_and_read_body_json(&app, list("q=HELLO")).await;
+        assert_eq!(names(&body), [folders[0].clone(), folders[2].clone()]);
+        let res = test::call_service(&app, list("q=hello&limit=1")).await;
+        assert_eq!(
+            res.headers()
+                .get(NEXT_CURSOR_HEADER)
+                .unwrap()
+                .to_str()
+                .unwrap(),
+            folders[0]
+        );
+
+        let bad = test::call_service(&app, list("limit=0")).await;
+        assert_eq!(bad.status(), 400);
+        let bad = test::call_service(&app, list("limit=201")).await;
+        assert_eq!(bad.status(), 400);
+        let bad = test::call_service(&app, list("cursor=nope")).await;
+        assert_eq!(bad.status(), 400);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
 }
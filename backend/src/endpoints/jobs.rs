@@ -0,0 +1,106 @@
+use actix_web::{
+    get,
+    web::{self, Bytes, Data, ServiceConfig},
+    HttpResponse,
+};
+use futures::stream;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::timeout;
+
+use crate::app_state::AppState;
+use crate::error::AppError;
+use crate::services::auth::Identity;
+use crate::services::jobs::Job;
+
+/// Send a comment line this often so proxies keep an idle stream open.
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// GET /jobs/{id}
+/// Returns the current status of one of the caller's speech or video jobs.
+#[get("/jobs/{id}")]
+async fn get_job(
+    state: Data<AppState>,
+    identity: Identity,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    match state.jobs.get(&identity.user_id, &path.into_inner()) {
+        Some(job) => Ok(HttpResponse::Ok().json(job)),
+        None => Err(AppError::NotFound("job not found".to_string())),
+    }
+}
+
+/// GET /jobs/{id}/events
+/// Server-Sent Events stream of job snapshots. Emits the current status
+/// immediately, then one `status` event per change (chunk completed, merge,
+/// ffmpeg encode progress) and closes once the job is done or failed.
+#[get("/jobs/{id}/events")]
+async fn job_events(
+    state: Data<AppState>,
+    identity: Identity,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let Some(rx) = state.jobs.subscribe(&identity.user_id, &path.into_inner()) else {
+        return Err(AppError::NotFound("job not found".to_string()));
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(job_event_stream(rx)))
+}
+
+/// State carried between polls of the SSE stream.
+enum Cursor {
+    /// Nothing sent yet: emit the current snapshot straight away.
+    Start(watch::Receiver<Job>),
+    /// Waiting for the next change.
+    Waiting(watch::Receiver<Job>),
+    /// The final event has been sent.
+    Closed,
+}
+
+fn job_event_stream(
+    rx: watch::Receiver<Job>,
+) -> impl futures::Stream<Item = Result<Bytes, actix_web::Error>> {
+    stream::unfold(Cursor::Start(rx), |cursor| async move {
+        let mut rx = match cursor {
+            Cursor::Start(mut rx) => {
+                let job = rx.borrow_and_update().clone();
+                return Some((Ok(status_event(&job)), next_cursor(&job, rx)));
+            }
+            Cursor::Waiting(rx) => rx,
+            Cursor::Closed => return None,
+        };
+
+        match timeout(SSE_KEEP_ALIVE, rx.changed()).await {
+            Err(_) => Some((
+                Ok(Bytes::from_static(b": keep-alive\n\n")),
+                Cursor::Waiting(rx),
+            )),
+            // The job was pruned from the table; nothing more will arrive.
+            Ok(Err(_)) => None,
+            Ok(Ok(())) => {
+                let job = rx.borrow_and_update().clone();
+                Some((Ok(status_event(&job)), next_cursor(&job, rx)))
+            }
+        }
+    })
+}
+
+fn next_cursor(job: &Job, rx: watch::Receiver<Job>) -> Cursor {
+    if job.status.is_finished() {
+        Cursor::Closed
+    } else {
+        Cursor::Waiting(rx)
+    }
+}
+
+fn status_event(job: &Job) -> Bytes {
+    let data = serde_json::to_string(job).unwrap_or_default();
+    Bytes::from(format!("event: status\ndata: {data}\n\n"))
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(get_job).service(job_events);
+}
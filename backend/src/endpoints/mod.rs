@@ -1,3 +1,8 @@
+pub mod cleanup;
 pub mod files;
+pub mod jobs;
+pub mod share;
 pub mod speech;
+pub mod streaming;
+pub mod usage;
 pub mod video;
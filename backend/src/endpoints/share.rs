@@ -0,0 +1,63 @@
+use actix_web::{
+    get,
+    http::header::{self, HeaderValue},
+    web::{self, Data},
+    HttpRequest, HttpResponse,
+};
+use tracing::info;
+
+use crate::app_state::AppState;
+use crate::endpoints::streaming::{response_length, stream_object};
+use crate::error::AppError;
+use crate::services::generation::content_type_for;
+use crate::services::storage::object_key;
+
+/// GET /share/{token}
+/// Serves the file a share link grants access to, without a session. The
+/// token's signature and expiry are checked first, and the bytes sent count
+/// against its limit only once the file has been found. Range requests are
+/// supported so shared videos can be seeked.
+#[get("/share/{token}")]
+pub async fn get_shared_file(
+    state: Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let grant = state
+        .share_links
+        .verify(&path.into_inner())
+        .inspect_err(|e| info!("Refused share link: {}", e))?;
+
+    // 1) Charge what this response sends against the link's limit, so
+    //    ranges can't fetch the file more often than allowed
+    let key = object_key(&grant.user_id, &grant.dir_name, &grant.file_name)?;
+    let meta = state.storage.head(&key).await?;
+    let length = response_length(&req, &meta);
+    state
+        .share_links
+        .record_download(&grant, length, meta.size)?;
+    info!(
+        "Serving {} bytes of shared {} (link {})",
+        length, key, grant.id
+    );
+
+    // 2) Stream it like any other output
+    let mut res = stream_object(
+        &req,
+        state.storage.as_ref(),
+        &key,
+        content_type_for(&grant.file_name),
+    )
+    .await?;
+    let headers = res.headers_mut();
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("private, no-store"),
+    );
+    if let Ok(disposition) =
+        HeaderValue::from_str(&format!("inline; filename=\"{}\"", grant.file_name))
+    {
+        headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+    Ok(res)
+}
@@ -0,0 +1,253 @@
+use actix_files::HttpRange;
+use actix_web::{
+    http::{
+        header::{self, EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, IfRange},
+        StatusCode,
+    },
+    web::Bytes,
+    HttpMessage, HttpRequest, HttpResponse,
+};
+use futures::TryStreamExt;
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::AppError;
+use crate::services::storage::{ObjectMeta, Storage, StoragePath};
+
+/// Stream the object at `key`, honoring `Range`, `If-Range`,
+/// `If-None-Match` and `If-Modified-Since` so players can seek without
+/// downloading the whole file first.
+pub(crate) async fn stream_object(
+    req: &HttpRequest,
+    storage: &dyn Storage,
+    key: &StoragePath,
+    content_type: &str,
+) -> Result<HttpResponse, AppError> {
+    // 1) Look up size and validators
+    let meta = storage.head(key).await?;
+    let etag = meta.etag.clone().map(EntityTag::new_strong);
+
+    let mut res = HttpResponse::Ok();
+    res.insert_header((header::ACCEPT_RANGES, "bytes"));
+    if let Some(etag) = &etag {
+        res.insert_header(header::ETag(etag.clone()));
+    }
+    if let Some(modified) = meta.last_modified {
+        res.insert_header(header::LastModified(HttpDate::from(modified)));
+    }
+
+    // 2) Conditional GET
+    if not_modified(req, etag.as_ref(), &meta) {
+        return Ok(res.status(StatusCode::NOT_MODIFIED).finish());
+    }
+
+    // 3) Pick the byte range to send
+    let range = match requested_range(req, etag.as_ref(), &meta) {
+        Ok(range) => range,
+        Err(()) => {
+            return Ok(res
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .insert_header((header::CONTENT_RANGE, format!("bytes */{}", meta.size)))
+                .finish());
+        }
+    };
+    if let Some(range) = &range {
+        res.status(StatusCode::PARTIAL_CONTENT).insert_header((
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", range.start, range.end - 1, meta.size),
+        ));
+    }
+    let length = range.as_ref().map_or(meta.size, |r| r.end - r.start);
+
+    // 4) Stream it
+    let body = storage.stream(key, range).await?;
+    Ok(res
+        .content_type(content_type.to_string())
+        .no_chunking(length)
+        .streaming(body.map_ok(Bytes::from)))
+}
+
+/// Bytes the response to `req` will carry: none for a `304` or `416`, the
+/// range's length for a `206` and the whole object otherwise.
+pub(crate) fn response_length(req: &HttpRequest, meta: &ObjectMeta) -> u64 {
+    let etag = meta.etag.clone().map(EntityTag::new_strong);
+    if not_modified(req, etag.as_ref(), meta) {
+        return 0;
+    }
+    match requested_range(req, etag.as_ref(), meta) {
+        Ok(Some(range)) => range.end - range.start,
+        Ok(None) => meta.size,
+        Err(()) => 0,
+    }
+}
+
+/// Whether the client's cached copy is still current. `If-None-Match` wins
+/// over `If-Modified-Since` when both are sent.
+fn not_modified(req: &HttpRequest, etag: Option<&EntityTag>, meta: &ObjectMeta) -> bool {
+    match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => {
+            etag.is_some_and(|etag| tags.iter().any(|tag| tag.weak_eq(etag)))
+        }
+        None => match (req.get_header::<IfModifiedSince>(), meta.last_modified) {
+            (Some(IfModifiedSince(since)), Some(modified)) => {
+                unix_secs(modified) <= unix_secs(since.into())
+            }
+            _ => false,
+        },
+    }
+}
+
+/// The range asked for by `Range`, or `None` to send the whole object.
+/// Only the first range of a multi-range request is served. `Err` means the
+/// range lies outside the object.
+fn requested_range(
+    req: &HttpRequest,
+    etag: Option<&EntityTag>,
+    meta: &ObjectMeta,
+) -> Result<Option<Range<u64>>, ()> {
+    let Some(header) = req.headers().get(header::RANGE) else {
+        return Ok(None);
+    };
+    // A stale `If-Range` means the client's partial copy is outdated, so
+    // it gets the whole file instead.
+    let current = match req.get_header::<IfRange>() {
+        None => true,
+        Some(IfRange::EntityTag(tag)) => etag.is_some_and(|etag| tag.strong_eq(etag)),
+        Some(IfRange::Date(date)) => meta
+            .last_modified
+            .is_some_and(|modified| unix_secs(modified) == unix_secs(date.into())),
+    };
+    if !current {
+        return Ok(None);
+    }
+
+    let Ok(header) = header.to_str() else {
+        return Ok(None);
+    };
+    match HttpRange::parse(header, meta.size) {
+        Ok(ranges) => Ok(ranges
+            .first()
+            .map(|range| range.start..range.start + range.length)),
+        Err(_) => Err(()),
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::local_storage::LocalStorage;
+    use actix_web::{test, web, App};
+    use std::sync::Arc;
+
+    async fn serve(
+        req: HttpRequest,
+        storage: web::Data<Arc<LocalStorage>>,
+    ) -> Result<HttpResponse, AppError> {
+        stream_object(
+            &req,
+            storage.as_ref().as_ref(),
+            &"u/d/final.mp3".parse().unwrap(),
+            "audio/mpeg",
+        )
+        .await
+    }
+
+    #[actix_web::test]
+    async fn honors_ranges_and_validators() {
+        let dir = std::env::temp_dir().join(format!("streaming-{}", std::process::id()));
+        let storage = Arc::new(LocalStorage::new(&dir).unwrap());
+        storage
+            .put(
+                &"u/d/final.mp3".parse().unwrap(),
+                b"0123456789".to_vec(),
+                "audio/mpeg",
+            )
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(storage))
+                .default_service(web::to(serve)),
+        )
+        .await;
+        let get = |headers: &[(header::HeaderName, String)]| {
+            let mut req = test::TestRequest::get().uri("/");
+            for (name, value) in headers {
+                req = req.insert_header((name.clone(), value.clone()));
+            }
+            req.to_request()
+        };
+
+        // Whole file, with validators
+        let full = test::call_service(&app, get(&[])).await;
+        assert_eq!(full.status(), 200);
+        assert_eq!(full.headers().get(header::ACCEPT_RANGES).unwrap(), "bytes");
+        let etag = full
+            .headers()
+            .get(header::ETAG)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let modified = full
+            .headers()
+            .get(header::LAST_MODIFIED)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(test::read_body(full).await, "0123456789");
+
+        // Ranges
+        let part = test::call_service(&app, get(&[(header::RANGE, "bytes=2-4".into())])).await;
+        assert_eq!(part.status(), 206);
+        assert_eq!(
+            part.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes 2-4/10"
+        );
+        assert_eq!(test::read_body(part).await, "234");
+        let suffix = test::call_service(&app, get(&[(header::RANGE, "bytes=-3".into())])).await;
+        assert_eq!(test::read_body(suffix).await, "789");
+        let outside = test::call_service(&app, get(&[(header::RANGE, "bytes=20-".into())])).await;
+        assert_eq!(outside.status(), 416);
+        assert_eq!(
+            outside.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes */10"
+        );
+
+        // Conditional requests
+        let cached = test::call_service(&app, get(&[(header::IF_NONE_MATCH, etag.clone())])).await;
+        assert_eq!(cached.status(), 304);
+        let changed =
+            test::call_service(&app, get(&[(header::IF_NONE_MATCH, "\"other\"".into())])).await;
+        assert_eq!(changed.status(), 200);
+        let unchanged =
+            test::call_service(&app, get(&[(header::IF_MODIFIED_SINCE, modified)])).await;
+        assert_eq!(unchanged.status(), 304);
+        let stale_range = test::call_service(
+            &app,
+            get(&[
+                (header::RANGE, "bytes=2-4".into()),
+                (header::IF_RANGE, "\"other\"".into()),
+            ]),
+        )
+        .await;
+        assert_eq!(stale_range.status(), 200);
+        let fresh_range = test::call_service(
+            &app,
+            get(&[
+                (header::RANGE, "bytes=2-4".into()),
+                (header::IF_RANGE, etag),
+            ]),
+        )
+        .await;
+        assert_eq!(fresh_range.status(), 206);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
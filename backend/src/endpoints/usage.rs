@@ -0,0 +1,125 @@
+use actix_web::{
+    get,
+    web::{Data, ServiceConfig},
+    HttpResponse,
+};
+use serde_json::json;
+use tracing::info;
+
+use crate::app_state::AppState;
+use crate::error::AppError;
+use crate::services::auth::Identity;
+use crate::services::quota::Usage;
+
+/// What `user_id` has stored plus what their queued and running jobs are
+/// expected to add.
+async fn current_usage(state: &AppState, user_id: &str) -> Result<Usage, AppError> {
+    let mut usage = state.quota.usage(state.storage.as_ref(), user_id).await?;
+    let (jobs, chars) = state.jobs.pending(user_id);
+    usage.add_pending(jobs, chars);
+    Ok(usage)
+}
+
+/// Refuse new work for a user who is over quota: `507 Insufficient Storage`
+/// for stored bytes, `429 Too Many Requests` for generated audio. Jobs that
+/// haven't finished yet count too.
+pub(crate) async fn enforce_quota(state: &AppState, user_id: &str) -> Result<(), AppError> {
+    let usage = current_usage(state, user_id).await?;
+    state.quota.check(&usage).map_err(|error| {
+        info!("Rejecting job for {}: {}", user_id, error);
+        AppError::QuotaExceeded {
+            error,
+            usage,
+            limits: state.quota.limits().clone(),
+        }
+    })
+}
+
+/// GET /usage
+/// The caller's stored bytes, generated audio seconds and run count, with
+/// the limits that apply (`null` when unlimited) and the TTS rate limits
+/// jobs are synthesized under. Unfinished jobs are included as estimates.
+#[get("/usage")]
+async fn get_usage(state: Data<AppState>, identity: Identity) -> Result<HttpResponse, AppError> {
+    let usage = current_usage(&state, &identity.user_id).await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "usage": usage,
+        "limits": state.quota.limits(),
+        "tts_limits": state.tts_limiter.limits(),
+    })))
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(get_usage);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::test_state;
+    use crate::services::generation::{GenerationContext, SpeechRequest};
+    use crate::services::jobs::{JobQueue, JobSpec};
+    use crate::services::quota::{QuotaError, QuotaLimits, UsageTracker};
+    use crate::services::tts_service::{TtsError, TtsOptions, TtsProvider};
+    use async_trait::async_trait;
+    use std::sync::Arc;
+
+    /// Provider that never answers, so jobs stay in flight.
+    struct StalledTts;
+
+    #[async_trait]
+    impl TtsProvider for StalledTts {
+        fn name(&self) -> &'static str {
+            "stalled"
+        }
+
+        async fn synthesize(&self, _: &str, _: &str, _: &TtsOptions) -> Result<Vec<u8>, TtsError> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_unfinished_jobs_against_the_quota() {
+        let dir = std::env::temp_dir().join(format!("usage-pending-{}", std::process::id()));
+        let Ok(mut state) = Arc::try_unwrap(test_state(&dir, b"unused").into_inner()) else {
+            unreachable!("the state isn't shared yet");
+        };
+        state.quota = UsageTracker::new(QuotaLimits {
+            max_bytes: None,
+            max_audio_secs: Some(60.0),
+        });
+        state.jobs = JobQueue::start(
+            1,
+            GenerationContext {
+                tts: Arc::new(StalledTts),
+                storage: state.storage.clone(),
+            },
+        );
+        enforce_quota(&state, "alice").await.unwrap();
+
+        // Over a minute of speech, in flight but not stored yet
+        let request = SpeechRequest {
+            user_id: "alice".to_string(),
+            text: "word ".repeat(200),
+            voice: "alloy".to_string(),
+            options: TtsOptions::default(),
+        };
+        state.jobs.submit(JobSpec::Speech(request)).unwrap();
+        let usage = current_usage(&state, "alice").await.unwrap();
+        assert_eq!(usage.pending_jobs, 1);
+        let err = enforce_quota(&state, "alice").await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                AppError::QuotaExceeded {
+                    error: QuotaError::AudioSecs { .. },
+                    ..
+                }
+            ),
+            "{err}"
+        );
+        enforce_quota(&state, "bob").await.unwrap();
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,250 @@
+use actix_web::{
+    error::{JsonPayloadError, PathError, QueryPayloadError},
+    http::StatusCode,
+    HttpRequest, HttpResponse, ResponseError,
+};
+use serde_json::json;
+use std::fmt;
+
+use crate::middleware::request_id;
+use crate::services::auth::AuthError;
+use crate::services::quota::{QuotaError, QuotaLimits, Usage};
+use crate::services::share_links::ShareError;
+use crate::services::storage::StorageError;
+
+/// Every error an endpoint can answer with.
+///
+/// The response body is `{"error": <message>, "code": <code>, "request_id":
+/// <id>}`. `code` is stable and meant for programs; the message is for people
+/// and may change. `request_id` matches the `X-Request-Id` header and the
+/// server logs.
+#[derive(Debug)]
+pub enum AppError {
+    /// The request is malformed or asks for something unsupported.
+    InvalidInput(String),
+    /// No valid session token.
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    /// The target exists but can't take the change.
+    Conflict(String),
+    /// A share link that can't be used.
+    ShareLink(ShareError),
+    /// The user is over one of their quotas.
+    QuotaExceeded {
+        error: QuotaError,
+        usage: Usage,
+        limits: QuotaLimits,
+    },
+    /// The job queue or the TTS provider isn't accepting work.
+    Unavailable(String),
+    /// The storage backend failed.
+    Storage(String),
+    /// ffmpeg or another media tool failed.
+    Media(String),
+    Internal(String),
+}
+
+impl AppError {
+    /// Machine-readable code sent as `code`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::InvalidInput(_) => "invalid_input",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::ShareLink(ShareError::Malformed | ShareError::InvalidSignature) => {
+                "share_link_invalid"
+            }
+            AppError::ShareLink(ShareError::Expired) => "share_link_expired",
+            AppError::ShareLink(ShareError::LimitReached) => "share_link_used_up",
+            AppError::QuotaExceeded {
+                error: QuotaError::Bytes { .. },
+                ..
+            } => "storage_quota_exceeded",
+            AppError::QuotaExceeded {
+                error: QuotaError::AudioSecs { .. },
+                ..
+            } => "audio_quota_exceeded",
+            AppError::Unavailable(_) => "unavailable",
+            AppError::Storage(_) => "storage_error",
+            AppError::Media(_) => "media_error",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::InvalidInput(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::NotFound(msg)
+            | AppError::Conflict(msg)
+            | AppError::Unavailable(msg)
+            | AppError::Storage(msg)
+            | AppError::Media(msg)
+            | AppError::Internal(msg) => f.write_str(msg),
+            AppError::ShareLink(e) => write!(f, "{e}"),
+            AppError::QuotaExceeded { error, .. } => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::ShareLink(ShareError::Malformed | ShareError::InvalidSignature) => {
+                StatusCode::FORBIDDEN
+            }
+            AppError::ShareLink(ShareError::Expired | ShareError::LimitReached) => StatusCode::GONE,
+            AppError::QuotaExceeded {
+                error: QuotaError::Bytes { .. },
+                ..
+            } => StatusCode::INSUFFICIENT_STORAGE,
+            AppError::QuotaExceeded {
+                error: QuotaError::AudioSecs { .. },
+                ..
+            } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Storage(_) | AppError::Media(_) | AppError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        let request_id = request_id::current();
+        if status.is_server_error() {
+            tracing::error!(
+                "Request {} failed: {}",
+                request_id.as_deref().unwrap_or("-"),
+                self
+            );
+        }
+
+        let mut body = json!({
+            "error": self.to_string(),
+            "code": self.code(),
+        });
+        if let Some(id) = request_id {
+            body["request_id"] = json!(id);
+        }
+        if let AppError::QuotaExceeded { usage, limits, .. } = self {
+            body["usage"] = json!(usage);
+            body["limits"] = json!(limits);
+        }
+        HttpResponse::build(status).json(body)
+    }
+}
+
+impl From<StorageError> for AppError {
+    fn from(e: StorageError) -> Self {
+        let msg = e.to_string();
+        match e {
+            StorageError::InvalidKey(_) => AppError::InvalidInput(msg),
+            StorageError::NotFound => AppError::NotFound(msg),
+            StorageError::Forbidden => AppError::Forbidden(msg),
+            StorageError::Backend(_) => AppError::Storage(msg),
+        }
+    }
+}
+
+impl From<ShareError> for AppError {
+    fn from(e: ShareError) -> Self {
+        AppError::ShareLink(e)
+    }
+}
+
+impl From<AuthError> for AppError {
+    fn from(e: AuthError) -> Self {
+        AppError::Unauthorized(e.to_string())
+    }
+}
+
+/// Answer unparseable JSON bodies with an `invalid_input` error.
+pub fn json_error_handler(e: JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    AppError::InvalidInput(e.to_string()).into()
+}
+
+/// Answer unparseable query strings with an `invalid_input` error.
+pub fn query_error_handler(e: QueryPayloadError, _: &HttpRequest) -> actix_web::Error {
+    AppError::InvalidInput(e.to_string()).into()
+}
+
+/// Answer unparseable path segments with an `invalid_input` error.
+pub fn path_error_handler(e: PathError, _: &HttpRequest) -> actix_web::Error {
+    AppError::InvalidInput(e.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::request_id::{request_id, REQUEST_ID_HEADER};
+    use actix_web::{get, middleware::from_fn, test, web, App};
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Page {
+        limit: usize,
+    }
+
+    #[get("/missing")]
+    async fn missing() -> Result<HttpResponse, AppError> {
+        Err(StorageError::NotFound.into())
+    }
+
+    #[get("/page")]
+    async fn page(query: web::Query<Page>) -> HttpResponse {
+        HttpResponse::Ok().body(query.limit.to_string())
+    }
+
+    #[actix_web::test]
+    async fn errors_carry_code_and_request_id() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::QueryConfig::default().error_handler(query_error_handler))
+                .wrap(from_fn(request_id))
+                .service(missing)
+                .service(page),
+        )
+        .await;
+
+        let res =
+            test::call_service(&app, test::TestRequest::get().uri("/missing").to_request()).await;
+        assert_eq!(res.status(), 404);
+        let id = res
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["code"], "not_found");
+        assert_eq!(body["error"], "not found");
+        assert_eq!(body["request_id"], id.as_str());
+
+        // A caller's own id is kept, so logs can be correlated across services
+        let req = test::TestRequest::get()
+            .uri("/page?limit=many")
+            .insert_header((REQUEST_ID_HEADER, "trace-42"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), 400);
+        assert_eq!(res.headers().get(REQUEST_ID_HEADER).unwrap(), "trace-42");
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["code"], "invalid_input");
+        assert_eq!(body["request_id"], "trace-42");
+    }
+}
@@ -1,60 +1,272 @@
-use actix_files::Files;
 use actix_web::{
+    middleware::from_fn,
     web::{self, ServiceConfig},
 };
+use std::sync::Arc;
 
+use endpoints::cleanup::configure as cleanup_configure;
 use endpoints::files::configure as files_configure;
+use endpoints::jobs::configure as jobs_configure;
+use endpoints::share::get_shared_file;
 use endpoints::speech::get_speech;
+use endpoints::usage::configure as usage_configure;
 use endpoints::video::get_video;
+use error::{json_error_handler, path_error_handler, query_error_handler};
+use middleware::auth::authenticate;
+use middleware::request_id::request_id;
+use services::auth::{ClerkJwks, HmacJwt, IdentityProvider, CLERK_JWKS_URL};
+use services::generation::GenerationContext;
+use services::http_client::{build_client, HttpClientConfig};
+use services::jobs::JobQueue;
+use services::local_storage::LocalStorage;
+use services::mock_tts::MockTts;
+use services::quota::{QuotaLimits, UsageTracker};
+use services::retention::{self, RetentionPolicy};
+use services::s3_storage::{S3Config, S3Storage};
+use services::share_links::ShareLinks;
+use services::storage::Storage;
+use services::tts_limiter::{LimitedTts, TtsLimiter, TtsLimits};
+use services::tts_service::{OpenAiTts, RetryPolicy, RetryingTts, TtsProvider};
+use utils::folder_names::migrate_legacy_folders;
 
 use shuttle_actix_web::ShuttleActixWeb;
 use shuttle_runtime::SecretStore;
 mod app_state;
 mod endpoints;
+mod error;
+mod middleware;
 mod services;
 mod utils;
 
+/// Number of jobs processed concurrently unless `JOB_WORKERS` is set.
+const DEFAULT_JOB_WORKERS: usize = 2;
+
+/// Minutes between cleanup passes unless `CLEANUP_INTERVAL_MINS` is set.
+const DEFAULT_CLEANUP_INTERVAL_MINS: u64 = 60;
 
 #[shuttle_runtime::main]
 async fn main(
     #[shuttle_runtime::Secrets] secrets: SecretStore,
 ) -> ShuttleActixWeb<impl FnOnce(&mut ServiceConfig) + Send + Clone + 'static> {
-    let app_config = move |cfg: &mut ServiceConfig| {
-        let open_api_key = secrets
-            .get("OPENAI_API_KEY")
-            .expect("OpenAI API key is not set");
+    // One outbound client, kept in `AppState`, so every TTS call, S3 request
+    // and JWKS fetch reuses the same connection pool.
+    let default_http = HttpClientConfig::default();
+    let http_config = HttpClientConfig {
+        connect_timeout: secrets
+            .get("HTTP_CONNECT_TIMEOUT_SECS")
+            .and_then(|v| v.parse().ok())
+            .map_or(default_http.connect_timeout, std::time::Duration::from_secs),
+        timeout: secrets
+            .get("HTTP_TIMEOUT_SECS")
+            .and_then(|v| v.parse().ok())
+            .map_or(default_http.timeout, std::time::Duration::from_secs),
+        proxy: secrets.get("HTTP_PROXY_URL").filter(|p| !p.is_empty()),
+        ..default_http
+    };
+    tracing::info!("Outbound HTTP: {:?}", http_config);
+    let http = build_client(&http_config).expect("Invalid HTTP client configuration");
 
-        std::env::set_var("OPENAI_API_KEY", open_api_key);
+    // `TTS_PROVIDER = "mock"` runs fully offline with generated silence.
+    let tts: Arc<dyn TtsProvider> = match secrets.get("TTS_PROVIDER").as_deref() {
+        Some("mock") => Arc::new(MockTts::new()),
+        Some("openai") | None => {
+            let open_api_key = secrets
+                .get("OPENAI_API_KEY")
+                .expect("OpenAI API key is not set");
+            let base_url = secrets
+                .get("OPENAI_BASE_URL")
+                .filter(|u| !u.is_empty())
+                .unwrap_or_else(|| OpenAiTts::DEFAULT_BASE_URL.to_string());
+            Arc::new(OpenAiTts::new(http.clone(), open_api_key, &base_url))
+        }
+        Some(other) => panic!("Unknown TTS_PROVIDER: {other}"),
+    };
+    tracing::info!("Using TTS provider: {}", tts.name());
 
-        // Authentication removed
+    // Every TTS call, from any job, shares one concurrency and rate limit.
+    let default_limits = TtsLimits::default();
+    let tts_limiter = Arc::new(TtsLimiter::new(TtsLimits {
+        max_concurrent: secrets
+            .get("TTS_MAX_CONCURRENCY")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default_limits.max_concurrent),
+        requests_per_min: secrets
+            .get("TTS_REQUESTS_PER_MIN")
+            .and_then(|v| v.parse().ok()),
+        chars_per_min: secrets
+            .get("TTS_CHARS_PER_MIN")
+            .and_then(|v| v.parse().ok()),
+    }));
+    tracing::info!("TTS limits: {:?}", tts_limiter.limits());
+    let tts: Arc<dyn TtsProvider> = Arc::new(LimitedTts::new(tts, tts_limiter.clone()));
 
-        // Create `./user_files` so that Actix won't throw an error.
-        // If it already exists, `create_dir_all` does nothing.
-        if let Err(e) = std::fs::create_dir_all("./user_files") {
-            tracing::error!("Failed to create user_files directory: {:?}", e);
-            // Or handle the error however you'd like
+    // Transient failures (429, 5xx, network) are retried with backoff. Each
+    // attempt goes through the limiter again, and sleeps outside of it.
+    let default_retry = RetryPolicy::default();
+    let retry = RetryPolicy {
+        max_retries: secrets
+            .get("TTS_MAX_RETRIES")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default_retry.max_retries),
+        ..default_retry
+    };
+    let tts: Arc<dyn TtsProvider> = Arc::new(RetryingTts::new(tts, retry));
+
+    // `STORAGE_BACKEND = "s3"` keeps outputs in a bucket that survives
+    // redeploys; the default is the local `./user_files` directory.
+    let storage: Arc<dyn Storage> = match secrets.get("STORAGE_BACKEND").as_deref() {
+        Some("local") | None => {
+            // Rename "YYYY-MM-DD-HH:MM" output folders from older versions to ULIDs.
+            let root = std::path::Path::new("./user_files");
+            match migrate_legacy_folders(root) {
+                0 => {}
+                n => tracing::info!("Migrated {} legacy output folder(s)", n),
+            }
+            Arc::new(LocalStorage::new(root).expect("Failed to create user_files directory"))
+        }
+        Some("s3") => {
+            let secret = |key: &str| {
+                secrets
+                    .get(key)
+                    .unwrap_or_else(|| panic!("{key} is not set"))
+            };
+            let config = S3Config {
+                endpoint: secret("S3_ENDPOINT"),
+                bucket: secret("S3_BUCKET"),
+                region: secrets
+                    .get("S3_REGION")
+                    .unwrap_or_else(|| "us-east-1".to_string()),
+                access_key_id: secret("S3_ACCESS_KEY_ID"),
+                secret_access_key: secret("S3_SECRET_ACCESS_KEY"),
+            };
+            // Legacy folder names aren't migrated in a bucket; listings still
+            // read their creation time from the name.
+            Arc::new(S3Storage::new(http.clone(), config).expect("Invalid S3 configuration"))
         }
+        Some(other) => panic!("Unknown STORAGE_BACKEND: {other}"),
+    };
+    tracing::info!("Using storage backend: {}", storage.name());
 
-        let state = web::Data::new(app_state::AppState);
+    // Every /api request must carry a session token. Clerk tokens are checked
+    // against the instance's JWKS; `AUTH_PROVIDER = "hmac"` accepts HS256
+    // tokens signed with `AUTH_HMAC_SECRET` for local development.
+    let auth: Arc<dyn IdentityProvider> = match secrets.get("AUTH_PROVIDER").as_deref() {
+        Some("clerk") | None => Arc::new(ClerkJwks::new(
+            secrets
+                .get("CLERK_JWKS_URL")
+                .unwrap_or_else(|| CLERK_JWKS_URL.to_string()),
+            secrets.get("CLERK_SECRET_KEY").filter(|k| !k.is_empty()),
+            secrets.get("CLERK_ISSUER").filter(|i| !i.is_empty()),
+        )),
+        Some("hmac") => {
+            let secret = secrets
+                .get("AUTH_HMAC_SECRET")
+                .filter(|s| !s.is_empty())
+                .expect("AUTH_HMAC_SECRET is not set");
+            Arc::new(HmacJwt::new(secret.as_bytes()))
+        }
+        Some(other) => panic!("Unknown AUTH_PROVIDER: {other}"),
+    };
+    tracing::info!("Using identity provider: {}", auth.name());
 
-        cfg.service(
-            Files::new("/user_files", "./user_files")
-                .prefer_utf8(true)
-                .use_last_modified(true)
-                .show_files_listing(), // Optional for debugging (exposes file listing)
-        );
+    // Share links are signed with `SHARE_LINK_SECRET`. Without one a random
+    // key is used, and links stop working on restart.
+    let share_links = match secrets.get("SHARE_LINK_SECRET").filter(|s| !s.is_empty()) {
+        Some(secret) => ShareLinks::new(secret.as_bytes()),
+        None => {
+            tracing::warn!("SHARE_LINK_SECRET is not set; share links won't survive a restart");
+            ShareLinks::with_random_key()
+        }
+    };
+
+    // The job table and its workers must be shared by every Actix worker,
+    // so they are created once here rather than inside `app_config`.
+    let job_workers = secrets
+        .get("JOB_WORKERS")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_JOB_WORKERS);
+    let ctx = GenerationContext {
+        tts,
+        storage: storage.clone(),
+    };
+    let jobs = JobQueue::start(job_workers, ctx);
 
+    // Old runs are only purged when a limit is configured; leftover chunk
+    // files always are.
+    let retention = RetentionPolicy {
+        max_age_days: secrets
+            .get("RETENTION_MAX_AGE_DAYS")
+            .and_then(|v| v.parse().ok()),
+        max_runs_per_user: secrets
+            .get("RETENTION_MAX_RUNS_PER_USER")
+            .and_then(|v| v.parse().ok()),
+    };
+    let cleanup_interval_mins = secrets
+        .get("CLEANUP_INTERVAL_MINS")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_CLEANUP_INTERVAL_MINS)
+        .max(1);
+    tracing::info!(
+        "Retention: {:?}, cleanup every {} min",
+        retention,
+        cleanup_interval_mins
+    );
+    retention::start(
+        storage.clone(),
+        retention.clone(),
+        std::time::Duration::from_secs(cleanup_interval_mins * 60),
+    );
+    // Quotas are off unless configured.
+    let quota = UsageTracker::new(QuotaLimits {
+        max_bytes: secrets
+            .get("QUOTA_MAX_MB")
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(|mb| mb * 1024 * 1024),
+        max_audio_secs: secrets
+            .get("QUOTA_MAX_AUDIO_MINUTES")
+            .and_then(|v| v.parse::<f64>().ok())
+            .map(|minutes| minutes * 60.0),
+    });
+    tracing::info!("Quota limits: {:?}", quota.limits());
+
+    let state = web::Data::new(app_state::AppState {
+        jobs,
+        storage,
+        auth,
+        share_links,
+        retention,
+        quota,
+        tts_limiter,
+        http,
+    });
+
+    // Generated files are only served through `/api/media`, which checks the
+    // caller owns them, and `/share`, which checks a signed link;
+    // `./user_files` is never exposed directly. Every response carries an
+    // `X-Request-Id`, and errors are JSON with a `code` and that id.
+    let app_config = move |cfg: &mut ServiceConfig| {
         cfg.service(
-            web::scope("/api")
-                .service(get_speech)
-                .service(get_video)
-                .configure(files_configure),
+            web::scope("")
+                .wrap(from_fn(request_id))
+                .service(
+                    web::scope("/api")
+                        .wrap(from_fn(authenticate))
+                        .service(get_speech)
+                        .service(get_video)
+                        .configure(files_configure)
+                        .configure(jobs_configure)
+                        .configure(cleanup_configure)
+                        .configure(usage_configure),
+                )
+                .service(get_shared_file)
+                // serve the build files from the frontend
+                .service(actix_files::Files::new("/", "./frontend/dist").index_file("index.html")),
         )
-        // serve the build files from the frontend
-        .service(actix_files::Files::new("/", "./frontend/dist").index_file("index.html"))
+        .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+        .app_data(web::QueryConfig::default().error_handler(query_error_handler))
+        .app_data(web::PathConfig::default().error_handler(path_error_handler))
         .app_data(state);
     };
 
     Ok(app_config.into())
 }
-
@@ -0,0 +1,123 @@
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web::Data,
+    Error, FromRequest, HttpMessage, HttpRequest, ResponseError,
+};
+use futures::future::{ready, Ready};
+use tracing::info;
+
+use crate::app_state::AppState;
+use crate::error::AppError;
+use crate::services::auth::{AuthError, Identity};
+
+/// Cookie Clerk's frontend SDK keeps the session token in.
+const SESSION_COOKIE: &str = "__session";
+
+/// Bearer token from the `Authorization` header, falling back to the session
+/// cookie so plain `<audio>` tags and `EventSource` work same-origin.
+fn session_token(req: &HttpRequest) -> Option<String> {
+    let header = req
+        .headers()
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|t| t.trim().to_string());
+    header.or_else(|| req.cookie(SESSION_COOKIE).map(|c| c.value().to_string()))
+}
+
+/// Verify the caller's token with the configured identity provider and put
+/// their [`Identity`] in the request extensions. Anything else gets a 401.
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(state) = req.app_data::<Data<AppState>>().cloned() else {
+        return Err(AppError::Internal("application state is missing".to_string()).into());
+    };
+
+    let verified = match session_token(req.request()) {
+        Some(token) => state.auth.verify(&state.http, &token).await,
+        None => Err(AuthError::Missing),
+    };
+    match verified {
+        Ok(identity) => {
+            req.extensions_mut().insert(identity);
+            next.call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+        Err(e) => {
+            info!("Rejected {} {}: {}", req.method(), req.path(), e);
+            let response = AppError::from(e).error_response();
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+/// Handlers behind [`authenticate`] take an `Identity` argument to learn who
+/// is calling.
+impl FromRequest for Identity {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Identity>()
+                .cloned()
+                .ok_or_else(|| AppError::Unauthorized("not authenticated".to_string()).into()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::{test_state, test_token};
+    use actix_web::{get, middleware::from_fn, test, web, App, Responder};
+
+    #[get("/whoami")]
+    async fn whoami(identity: Identity) -> impl Responder {
+        identity.user_id
+    }
+
+    #[actix_web::test]
+    async fn only_verified_callers_reach_handlers() {
+        let dir = std::env::temp_dir().join(format!("auth-mw-{}", std::process::id()));
+        let state = test_state(&dir, b"dev-secret");
+        let app = test::init_service(
+            App::new().app_data(state).service(
+                web::scope("/api")
+                    .wrap(from_fn(authenticate))
+                    .service(whoami),
+            ),
+        )
+        .await;
+
+        let anonymous = test::TestRequest::get().uri("/api/whoami").to_request();
+        assert_eq!(test::call_service(&app, anonymous).await.status(), 401);
+
+        let forged = test::TestRequest::get()
+            .uri("/api/whoami")
+            .insert_header((
+                "Authorization",
+                format!("Bearer {}", test_token(b"guess", "u1")),
+            ))
+            .to_request();
+        assert_eq!(test::call_service(&app, forged).await.status(), 401);
+
+        let signed_in = test::TestRequest::get()
+            .uri("/api/whoami")
+            .cookie(actix_web::cookie::Cookie::new(
+                SESSION_COOKIE,
+                test_token(b"dev-secret", "user_1"),
+            ))
+            .to_request();
+        let body = test::call_and_read_body(&app, signed_in).await;
+        assert_eq!(body, "user_1");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
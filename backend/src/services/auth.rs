@@ -0,0 +1,564 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use reqwest::Client;
+use ring::{hmac, signature};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn};
+
+use crate::utils::storage_path::validate_segment;
+
+/// Display name used when a token carries none.
+pub const DEFAULT_DISPLAY_NAME: &str = "User";
+/// Clerk's Backend API endpoint for the instance's signing keys.
+pub const CLERK_JWKS_URL: &str = "https://api.clerk.com/v1/jwks";
+
+/// Clock skew tolerated on `exp`/`nbf`, in seconds.
+const LEEWAY_SECS: i64 = 60;
+/// Cached signing keys are refetched after this long...
+const JWKS_TTL: Duration = Duration::from_secs(60 * 60);
+/// ...or when a token names an unknown key, but no more often than this.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+
+/// The caller, as established by an [`IdentityProvider`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    /// Token subject; also the top-level folder of the user's files.
+    pub user_id: String,
+    pub display_name: String,
+}
+
+/// Why a request could not be authenticated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// No bearer token or session cookie.
+    Missing,
+    Malformed(String),
+    InvalidSignature,
+    Expired,
+    NotYetValid,
+    WrongIssuer,
+    UnknownKey(String),
+    /// The provider's keys could not be fetched.
+    Unavailable(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Missing => f.write_str("missing bearer token"),
+            AuthError::Malformed(e) => write!(f, "malformed token: {e}"),
+            AuthError::InvalidSignature => f.write_str("invalid token signature"),
+            AuthError::Expired => f.write_str("token has expired"),
+            AuthError::NotYetValid => f.write_str("token is not valid yet"),
+            AuthError::WrongIssuer => f.write_str("token was issued by someone else"),
+            AuthError::UnknownKey(kid) => write!(f, "unknown signing key '{kid}'"),
+            AuthError::Unavailable(e) => write!(f, "signing keys unavailable: {e}"),
+        }
+    }
+}
+
+/// Verifies bearer tokens and says who the caller is.
+#[async_trait]
+pub trait IdentityProvider: Send + Sync {
+    /// Short identifier for logs.
+    fn name(&self) -> &'static str;
+
+    /// `http` is the application's shared client, for providers that fetch
+    /// their keys.
+    async fn verify(&self, http: &Client, token: &str) -> Result<Identity, AuthError>;
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+/// The claims we read. Clerk only adds name claims when the session token
+/// template includes them, so all of them are optional.
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    exp: Option<i64>,
+    nbf: Option<i64>,
+    iss: Option<String>,
+    name: Option<String>,
+    first_name: Option<String>,
+    given_name: Option<String>,
+    username: Option<String>,
+}
+
+/// A decoded but not yet verified JWT.
+struct Jwt<'a> {
+    header: JwtHeader,
+    claims: Claims,
+    /// `<header>.<payload>`, the bytes the signature covers.
+    signed: &'a str,
+    signature: Vec<u8>,
+}
+
+impl<'a> Jwt<'a> {
+    fn decode(token: &'a str) -> Result<Self, AuthError> {
+        let malformed = |what: &str| AuthError::Malformed(what.to_string());
+        let (signed, signature) = token
+            .rsplit_once('.')
+            .ok_or_else(|| malformed("not a JWT"))?;
+        let (header, claims) = signed
+            .split_once('.')
+            .ok_or_else(|| malformed("not a JWT"))?;
+
+        let part = |b64: &str| {
+            URL_SAFE_NO_PAD
+                .decode(b64)
+                .map_err(|_| malformed("invalid base64url"))
+        };
+        let header: JwtHeader = serde_json::from_slice(&part(header)?)
+            .map_err(|e| AuthError::Malformed(format!("header: {e}")))?;
+        let claims: Claims = serde_json::from_slice(&part(claims)?)
+            .map_err(|e| AuthError::Malformed(format!("claims: {e}")))?;
+
+        Ok(Jwt {
+            header,
+            claims,
+            signed,
+            signature: part(signature)?,
+        })
+    }
+
+    /// Check the time window and issuer, then build the caller's identity.
+    fn into_identity(self, issuer: Option<&str>) -> Result<Identity, AuthError> {
+        let claims = self.claims;
+        let now = Utc::now().timestamp();
+        let exp = claims
+            .exp
+            .ok_or_else(|| AuthError::Malformed("missing exp".to_string()))?;
+        if now > exp + LEEWAY_SECS {
+            return Err(AuthError::Expired);
+        }
+        if claims.nbf.is_some_and(|nbf| now + LEEWAY_SECS < nbf) {
+            return Err(AuthError::NotYetValid);
+        }
+        if issuer.is_some() && claims.iss.as_deref() != issuer {
+            return Err(AuthError::WrongIssuer);
+        }
+        // The subject names the user's storage folder.
+        validate_segment(&claims.sub)
+            .map_err(|_| AuthError::Malformed("unsupported subject".to_string()))?;
+
+        let display_name = [
+            claims.name,
+            claims.first_name,
+            claims.given_name,
+            claims.username,
+        ]
+        .into_iter()
+        .flatten()
+        .find(|n| !n.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_DISPLAY_NAME.to_string());
+        Ok(Identity {
+            user_id: claims.sub,
+            display_name,
+        })
+    }
+}
+
+/// HS256 tokens signed with a shared secret, for local development and
+/// tests.
+pub struct HmacJwt {
+    key: hmac::Key,
+}
+
+impl HmacJwt {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret),
+        }
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for HmacJwt {
+    fn name(&self) -> &'static str {
+        "hmac"
+    }
+
+    async fn verify(&self, _http: &Client, token: &str) -> Result<Identity, AuthError> {
+        let jwt = Jwt::decode(token)?;
+        if jwt.header.alg != "HS256" {
+            return Err(AuthError::Malformed(format!(
+                "unexpected alg {}",
+                jwt.header.alg
+            )));
+        }
+        hmac::verify(&self.key, jwt.signed.as_bytes(), &jwt.signature)
+            .map_err(|_| AuthError::InvalidSignature)?;
+        jwt.into_identity(None)
+    }
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kid: String,
+    kty: String,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+/// Public keys by `kid`, when they were fetched and when a fetch was last
+/// tried.
+#[derive(Default)]
+struct KeyCache {
+    keys: HashMap<String, signature::RsaPublicKeyComponents<Vec<u8>>>,
+    fetched_at: Option<Instant>,
+    attempted_at: Option<Instant>,
+}
+
+impl KeyCache {
+    fn age(&self) -> Option<Duration> {
+        self.fetched_at.map(|t| t.elapsed())
+    }
+
+    /// Whether a fetch, successful or not, started too recently for another.
+    fn refreshed_recently(&self) -> bool {
+        [self.fetched_at, self.attempted_at]
+            .into_iter()
+            .flatten()
+            .any(|t| t.elapsed() < JWKS_MIN_REFRESH)
+    }
+
+    fn get(&self, kid: &str) -> Result<signature::RsaPublicKeyComponents<Vec<u8>>, AuthError> {
+        self.keys
+            .get(kid)
+            .cloned()
+            .ok_or_else(|| AuthError::UnknownKey(kid.to_string()))
+    }
+}
+
+/// RS256 session tokens issued by Clerk (or any issuer publishing a JWKS),
+/// checked against the issuer's published signing keys.
+pub struct ClerkJwks {
+    jwks_url: String,
+    /// Sent as a bearer token when fetching keys from Clerk's Backend API.
+    secret_key: Option<String>,
+    /// Expected `iss`, e.g. `https://clerk.example.com`.
+    issuer: Option<String>,
+    cache: RwLock<KeyCache>,
+    /// Held while fetching, so only one request refreshes at a time.
+    refresh: Mutex<()>,
+}
+
+impl ClerkJwks {
+    pub fn new(jwks_url: String, secret_key: Option<String>, issuer: Option<String>) -> Self {
+        Self {
+            jwks_url,
+            secret_key,
+            issuer,
+            cache: RwLock::default(),
+            refresh: Mutex::default(),
+        }
+    }
+
+    /// Key `kid`, refetching the JWKS if it's stale or doesn't have it. If
+    /// the fetch fails, a key already in the cache is still used.
+    async fn key(
+        &self,
+        http: &Client,
+        kid: &str,
+    ) -> Result<signature::RsaPublicKeyComponents<Vec<u8>>, AuthError> {
+        {
+            let cache = self.cache.read().await;
+            let fresh = cache.age().is_some_and(|age| age < JWKS_TTL);
+            if let (true, Some(key)) = (fresh, cache.keys.get(kid)) {
+                return Ok(key.clone());
+            }
+        }
+
+        // 1) One fetch at a time. Another request may have refreshed while
+        //    we waited, or a fetch may have just failed.
+        let _refreshing = self.refresh.lock().await;
+        {
+            let mut cache = self.cache.write().await;
+            if cache.refreshed_recently() {
+                return cache.get(kid);
+            }
+            cache.attempted_at = Some(Instant::now());
+        }
+
+        // 2) Fetch without holding the cache, so cached keys keep being
+        //    served meanwhile, then swap the new keys in
+        match self.fetch_keys(http).await {
+            Ok(keys) => {
+                info!(
+                    "Fetched {} signing key(s) from {}",
+                    keys.len(),
+                    self.jwks_url
+                );
+                let mut cache = self.cache.write().await;
+                cache.keys = keys;
+                cache.fetched_at = Some(Instant::now());
+                cache.get(kid)
+            }
+            Err(e) => {
+                let cache = self.cache.read().await;
+                let key = cache.get(kid).map_err(|_| e.clone())?;
+                warn!(
+                    "Refreshing signing keys failed, using cached {}: {}",
+                    kid, e
+                );
+                Ok(key)
+            }
+        }
+    }
+
+    async fn fetch_keys(
+        &self,
+        http: &Client,
+    ) -> Result<HashMap<String, signature::RsaPublicKeyComponents<Vec<u8>>>, AuthError> {
+        let mut request = http.get(&self.jwks_url);
+        if let Some(secret_key) = &self.secret_key {
+            request = request.bearer_auth(secret_key);
+        }
+        let jwks: Jwks = request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| AuthError::Unavailable(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| AuthError::Unavailable(e.to_string()))?;
+
+        Ok(jwks
+            .keys
+            .into_iter()
+            .filter(|k| k.kty == "RSA")
+            .filter_map(|k| {
+                let n = URL_SAFE_NO_PAD.decode(k.n?).ok()?;
+                let e = URL_SAFE_NO_PAD.decode(k.e?).ok()?;
+                Some((k.kid, signature::RsaPublicKeyComponents { n, e }))
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for ClerkJwks {
+    fn name(&self) -> &'static str {
+        "clerk"
+    }
+
+    async fn verify(&self, http: &Client, token: &str) -> Result<Identity, AuthError> {
+        let jwt = Jwt::decode(token)?;
+        if jwt.header.alg != "RS256" {
+            return Err(AuthError::Malformed(format!(
+                "unexpected alg {}",
+                jwt.header.alg
+            )));
+        }
+        let kid = jwt
+            .header
+            .kid
+            .as_deref()
+            .ok_or_else(|| AuthError::Malformed("missing kid".to_string()))?;
+        let key = self.key(http, kid).await?;
+        key.verify(
+            &signature::RSA_PKCS1_2048_8192_SHA256,
+            jwt.signed.as_bytes(),
+            &jwt.signature,
+        )
+        .map_err(|_| AuthError::InvalidSignature)?;
+        jwt.into_identity(self.issuer.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn encode(value: &serde_json::Value) -> String {
+        URL_SAFE_NO_PAD.encode(value.to_string())
+    }
+
+    fn hs256_token(secret: &[u8], claims: serde_json::Value) -> String {
+        let signed = format!(
+            "{}.{}",
+            encode(&json!({"alg": "HS256", "typ": "JWT"})),
+            encode(&claims)
+        );
+        let tag = hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, secret),
+            signed.as_bytes(),
+        );
+        format!("{signed}.{}", URL_SAFE_NO_PAD.encode(tag.as_ref()))
+    }
+
+    fn in_an_hour() -> i64 {
+        Utc::now().timestamp() + 3600
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_hs256_tokens() {
+        let http = Client::new();
+        let provider = HmacJwt::new(b"dev-secret");
+        let token = hs256_token(
+            b"dev-secret",
+            json!({"sub": "user_2abc", "exp": in_an_hour(), "first_name": "Ada"}),
+        );
+        assert_eq!(
+            provider.verify(&http, &token).await.unwrap(),
+            Identity {
+                user_id: "user_2abc".to_string(),
+                display_name: "Ada".to_string(),
+            }
+        );
+
+        let anonymous = hs256_token(b"dev-secret", json!({"sub": "u1", "exp": in_an_hour()}));
+        assert_eq!(
+            provider
+                .verify(&http, &anonymous)
+                .await
+                .unwrap()
+                .display_name,
+            DEFAULT_DISPLAY_NAME
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_hs256_tokens() {
+        let http = Client::new();
+        let provider = HmacJwt::new(b"dev-secret");
+        let verify = |token: String| {
+            let (provider, http) = (&provider, &http);
+            async move { provider.verify(http, &token).await.unwrap_err() }
+        };
+
+        let wrong_key = hs256_token(b"other", json!({"sub": "u1", "exp": in_an_hour()}));
+        assert_eq!(verify(wrong_key).await, AuthError::InvalidSignature);
+
+        let expired = hs256_token(b"dev-secret", json!({"sub": "u1", "exp": 1_000_000}));
+        assert_eq!(verify(expired).await, AuthError::Expired);
+
+        let traversal = hs256_token(b"dev-secret", json!({"sub": "../u2", "exp": in_an_hour()}));
+        assert!(matches!(verify(traversal).await, AuthError::Malformed(_)));
+
+        let unsigned = format!(
+            "{}.{}.",
+            encode(&json!({"alg": "none"})),
+            encode(&json!({"sub": "u1", "exp": in_an_hour()}))
+        );
+        assert!(matches!(verify(unsigned).await, AuthError::Malformed(_)));
+        assert!(matches!(
+            verify("garbage".to_string()).await,
+            AuthError::Malformed(_)
+        ));
+    }
+
+    /// 2048-bit RSA key (PKCS#8 DER) generated for these tests only.
+    const TEST_RSA_PKCS8: &[u8] = include_bytes!("../../testdata/rsa-2048.pk8");
+
+    #[tokio::test]
+    async fn verifies_rs256_tokens_against_cached_keys() {
+        use ring::rand::SystemRandom;
+
+        let key_pair = signature::RsaKeyPair::from_pkcs8(TEST_RSA_PKCS8).unwrap();
+        let public = signature::RsaPublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+
+        let sign = |header: serde_json::Value, claims: serde_json::Value| {
+            let signed = format!("{}.{}", encode(&header), encode(&claims));
+            let mut sig = vec![0; key_pair.public().modulus_len()];
+            key_pair
+                .sign(
+                    &signature::RSA_PKCS1_SHA256,
+                    &SystemRandom::new(),
+                    signed.as_bytes(),
+                    &mut sig,
+                )
+                .unwrap();
+            format!("{signed}.{}", URL_SAFE_NO_PAD.encode(sig))
+        };
+
+        // Pre-seed the cache so no request goes to the (unreachable) URL.
+        let http = Client::new();
+        let provider = ClerkJwks::new(
+            "http://127.0.0.1:9/jwks".to_string(),
+            None,
+            Some("https://clerk.example.com".to_string()),
+        );
+        {
+            let mut cache = provider.cache.write().await;
+            cache.keys.insert("ins_1".to_string(), public);
+            cache.fetched_at = Some(Instant::now());
+        }
+
+        let claims = json!({
+            "sub": "user_2abc",
+            "exp": in_an_hour(),
+            "iss": "https://clerk.example.com",
+        });
+        let token = sign(json!({"alg": "RS256", "kid": "ins_1"}), claims.clone());
+        assert_eq!(
+            provider.verify(&http, &token).await.unwrap().user_id,
+            "user_2abc"
+        );
+
+        let tampered = token.replacen('.', ".e30", 1);
+        assert!(provider.verify(&http, &tampered).await.is_err());
+
+        let other_issuer = sign(
+            json!({"alg": "RS256", "kid": "ins_1"}),
+            json!({"sub": "user_2abc", "exp": in_an_hour(), "iss": "https://evil.example.com"}),
+        );
+        assert_eq!(
+            provider.verify(&http, &other_issuer).await,
+            Err(AuthError::WrongIssuer)
+        );
+
+        // Unknown kid within the refresh interval: no refetch, just rejected.
+        let unknown = sign(json!({"alg": "RS256", "kid": "ins_2"}), claims);
+        assert_eq!(
+            provider.verify(&http, &unknown).await,
+            Err(AuthError::UnknownKey("ins_2".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_cached_keys_when_a_refresh_fails() {
+        let key_pair = signature::RsaKeyPair::from_pkcs8(TEST_RSA_PKCS8).unwrap();
+        let http = Client::new();
+        let provider = ClerkJwks::new("http://127.0.0.1:9/jwks".to_string(), None, None);
+
+        // Nothing cached: the failed fetch is the answer
+        assert!(matches!(
+            provider.key(&http, "ins_1").await,
+            Err(AuthError::Unavailable(_))
+        ));
+
+        // Stale keys are refreshed, but still used when that fails
+        {
+            let mut cache = provider.cache.write().await;
+            cache.keys.insert(
+                "ins_1".to_string(),
+                signature::RsaPublicKeyComponents::<Vec<u8>>::from(key_pair.public()),
+            );
+            cache.attempted_at = None;
+        }
+        assert!(provider.key(&http, "ins_1").await.is_ok());
+        assert!(provider.cache.read().await.attempted_at.is_some());
+
+        // A fetch was just tried, so an unknown kid isn't worth another
+        assert_eq!(
+            provider.key(&http, "ins_2").await.err(),
+            Some(AuthError::UnknownKey("ins_2".to_string()))
+        );
+    }
+}
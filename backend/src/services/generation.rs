@@ -0,0 +1,544 @@
+use futures::future::join_all;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::task;
+use tracing::info;
+
+use crate::error::AppError;
+use crate::services::jobs::{JobHandle, JobKind, JobStatus};
+use crate::services::manifest::{self, Manifest, MANIFEST_FILE};
+use crate::services::retention::CHUNK_FILE_PREFIX;
+use crate::services::storage::{object_key, Storage, StorageError, StoragePath};
+use crate::services::tts_service::{AudioFormat, TtsError, TtsOptions, TtsProvider};
+use crate::utils::{
+    audio_metadata::{pcm_metadata, read_mp3_metadata, WAV_HEADER_LEN},
+    chunk_text_unicode::chunk_text_unicode,
+    concat_mp3::{concat_mp3, concat_raw},
+    convert_to_mp4::convert_to_mp4_with_progress,
+};
+
+/// Largest input a single TTS call accepts.
+pub const MAX_CHUNK_CHARS: usize = 4096;
+
+/// Everything a speech or video job needs to run.
+pub struct SpeechRequest {
+    /// Owner of the output folder.
+    pub user_id: String,
+    pub text: String,
+    pub voice: String,
+    pub options: TtsOptions,
+}
+
+/// The services every job runs against, shared by all job workers.
+#[derive(Clone)]
+pub struct GenerationContext {
+    pub tts: Arc<dyn TtsProvider>,
+    pub storage: Arc<dyn Storage>,
+}
+
+/// A finished job's output, stored in the job's folder.
+pub struct GeneratedFile {
+    pub file_name: String,
+    pub content_type: &'static str,
+}
+
+/// Create a local scratch directory named `name` and return its path.
+/// Chunks are merged and encoded there, since `concat_mp3` and ffmpeg need
+/// real files, before being uploaded to storage.
+pub fn create_work_dir(name: &str) -> Result<String, AppError> {
+    let path = std::env::temp_dir().join("tts-work").join(name);
+    info!("Creating directory: {}", path.display());
+
+    fs::create_dir_all(&path).map_err(|e| {
+        AppError::Internal(format!(
+            "Failed to create directory {}: {e}",
+            path.display()
+        ))
+    })?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Content type to store an output file with, from its extension.
+pub fn content_type_for(file_name: &str) -> &'static str {
+    match file_name.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("mp4") => "video/mp4",
+        Some("json") => "application/json",
+        Some(ext) => ext
+            .parse::<AudioFormat>()
+            .map_or("application/octet-stream", AudioFormat::content_type),
+        None => "application/octet-stream",
+    }
+}
+
+/// Chunk the text, synthesize every chunk in parallel and merge the results.
+/// Returns the merged audio file. A `manifest.json` describing the run is
+/// stored in the output folder (named after the job id, a ULID) whether or
+/// not it succeeds.
+pub async fn generate_speech(
+    ctx: &GenerationContext,
+    req: &SpeechRequest,
+    job: &JobHandle,
+) -> Result<GeneratedFile, AppError> {
+    generate(ctx, req, job, JobKind::Speech).await
+}
+
+/// Like [`generate_speech`], then encode the merged audio as an MP4 with a
+/// static cover image. Returns the MP4 file; the MP3 is kept next to it.
+pub async fn generate_video(
+    ctx: &GenerationContext,
+    req: &SpeechRequest,
+    job: &JobHandle,
+) -> Result<GeneratedFile, AppError> {
+    generate(ctx, req, job, JobKind::Video).await
+}
+
+/// The pipeline shared by both job kinds: chunk, synthesize in parallel,
+/// merge, encode (video only), then publish.
+async fn generate(
+    ctx: &GenerationContext,
+    req: &SpeechRequest,
+    job: &JobHandle,
+    kind: JobKind,
+) -> Result<GeneratedFile, AppError> {
+    // 1) Chunk text at Unicode boundaries
+    let chunks = chunk_text_unicode(&req.text, MAX_CHUNK_CHARS);
+    info!("Job {}: {} chunk(s) to synthesize", job.id(), chunks.len());
+    if chunks.is_empty() {
+        return Err(AppError::InvalidInput("No text provided.".to_string()));
+    }
+    let format = req.options.response_format;
+    if chunks.len() > 1 && !format.is_concatenable() {
+        return Err(AppError::InvalidInput(format!(
+            "response_format '{format}' only supports a single chunk of up to {MAX_CHUNK_CHARS} characters"
+        )));
+    }
+    job.set_status(JobStatus::Synthesizing {
+        chunk: 0,
+        total: chunks.len(),
+    });
+
+    let work_dir = create_work_dir(job.id())?;
+    let mut manifest = Manifest::new(job.id(), kind, ctx.tts.name(), req);
+    manifest.add_chunks(&chunks);
+
+    let mut result =
+        synthesize_and_merge(ctx.tts.clone(), req, job, chunks, &work_dir, &mut manifest).await;
+    if let (JobKind::Video, Ok(_)) = (kind, &result) {
+        result = encode_video(job, &work_dir, &mut manifest).await;
+    }
+    publish(
+        ctx.storage.as_ref(),
+        &req.user_id,
+        job.id(),
+        &work_dir,
+        &mut manifest,
+        result,
+    )
+    .await
+}
+
+async fn synthesize_and_merge(
+    tts: Arc<dyn TtsProvider>,
+    req: &SpeechRequest,
+    job: &JobHandle,
+    chunks: Vec<String>,
+    work_dir: &str,
+    manifest: &mut Manifest,
+) -> Result<&'static str, AppError> {
+    let format = req.options.response_format;
+    let total = chunks.len();
+
+    // 2) For each chunk, spawn a parallel TTS task
+    let completed = Arc::new(AtomicUsize::new(0));
+    let mut tasks = Vec::new();
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let index = i + 1;
+        let voice = req.voice.clone();
+        let options = req.options.clone();
+        let chunk_filename = format!(
+            "{}/{}{}.{}",
+            work_dir,
+            CHUNK_FILE_PREFIX,
+            index,
+            format.extension()
+        );
+        let tts = tts.clone();
+        let job = job.clone();
+        let completed = completed.clone();
+
+        info!(
+            "Spawning TTS task #{}, output file: {}",
+            index, chunk_filename
+        );
+
+        tasks.push(task::spawn(async move {
+            info!("Task #{}: calling TTS API ({})", index, tts.name());
+            let started = Instant::now();
+            let bytes = tts
+                .synthesize(&chunk, &voice, &options)
+                .await
+                .map_err(|e| tts_error(index, e))?;
+            let elapsed_ms = started.elapsed().as_millis() as u64;
+
+            fs::write(&chunk_filename, &bytes).map_err(|e| {
+                AppError::Internal(format!("Failed to write {chunk_filename}: {e}"))
+            })?;
+            info!("Task #{}: successfully wrote {}", index, chunk_filename);
+
+            let done = completed.fetch_add(1, Ordering::SeqCst) + 1;
+            job.set_status(JobStatus::Synthesizing { chunk: done, total });
+            Ok::<_, AppError>((chunk_filename, elapsed_ms))
+        }));
+    }
+
+    // 3) Wait for all tasks to finish, recording each outcome
+    let mut saved_files = Vec::new();
+    let mut first_error = None;
+    for (i, result) in join_all(tasks).await.into_iter().enumerate() {
+        let entry = &mut manifest.chunks[i];
+        match result {
+            Ok(Ok((filename, elapsed_ms))) => {
+                entry.file = Some(manifest::file_name(&filename));
+                entry.synthesis_ms = Some(elapsed_ms);
+                saved_files.push(filename);
+            }
+            Ok(Err(e)) => {
+                entry.error = Some(e.to_string());
+                first_error.get_or_insert(e);
+            }
+            Err(join_err) => {
+                entry.error = Some(join_err.to_string());
+                first_error.get_or_insert(AppError::Internal(format!(
+                    "Join error on task #{}: {join_err}",
+                    i + 1
+                )));
+            }
+        }
+    }
+    if let Some(e) = first_error {
+        return Err(e);
+    }
+
+    // 4) Merge the chunk files into one final file
+    job.set_status(JobStatus::Merging);
+    let started = Instant::now();
+    let final_path = format!("{}/final.{}", work_dir, format.extension());
+    info!(
+        "Merging {} chunk files into {}",
+        saved_files.len(),
+        final_path
+    );
+    let saved_files_ref: Vec<&str> = saved_files.iter().map(|s| s.as_str()).collect();
+    let merged = match format {
+        AudioFormat::Mp3 => concat_mp3(&saved_files_ref, &final_path),
+        _ => concat_raw(&saved_files_ref, &final_path),
+    };
+    merged.map_err(|e| AppError::Media(format!("Failed to merge audio: {e}")))?;
+    // The chunks are only needed for the merge; don't publish them
+    for file in &saved_files {
+        if let Err(e) = fs::remove_file(file) {
+            tracing::error!("Failed to remove {}: {:?}", file, e);
+        }
+    }
+    manifest.merge_ms = Some(started.elapsed().as_millis() as u64);
+    manifest.output = Some(manifest::file_name(&final_path));
+    // Formats without a cheap way to read their length are left to the
+    // quota's estimate from the input text
+    manifest.audio = match format {
+        AudioFormat::Mp3 => read_mp3_metadata(&final_path).ok(),
+        AudioFormat::Pcm => fs::metadata(&final_path)
+            .ok()
+            .map(|m| pcm_metadata(m.len(), 0)),
+        AudioFormat::Wav => fs::metadata(&final_path)
+            .ok()
+            .map(|m| pcm_metadata(m.len(), WAV_HEADER_LEN)),
+        AudioFormat::Opus | AudioFormat::Aac | AudioFormat::Flac => None,
+    };
+
+    Ok(format.content_type())
+}
+
+/// Encode the merged audio recorded in `manifest.output` as `final.mp4`.
+async fn encode_video(
+    job: &JobHandle,
+    work_dir: &str,
+    manifest: &mut Manifest,
+) -> Result<&'static str, AppError> {
+    let audio = manifest
+        .output
+        .clone()
+        .ok_or_else(|| AppError::Internal("No merged audio to encode".to_string()))?;
+    job.set_status(JobStatus::Encoding { progress: 0.0 });
+    let started = Instant::now();
+    let final_mp4_path = format!("{}/{}.mp4", work_dir, "final");
+    let (input, output) = (format!("{work_dir}/{audio}"), final_mp4_path.clone());
+    let encode_job = job.clone();
+    task::spawn_blocking(move || {
+        convert_to_mp4_with_progress(&input, &output, |progress| {
+            encode_job.set_status(JobStatus::Encoding { progress })
+        })
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Join error on ffmpeg task: {e}")))??;
+    manifest.encode_ms = Some(started.elapsed().as_millis() as u64);
+    manifest.output = Some(manifest::file_name(&final_mp4_path));
+
+    Ok(content_type_for(&final_mp4_path))
+}
+
+/// Map a failed TTS call for chunk `index` to the error the job fails with.
+/// Rate limits and provider outages are `unavailable`, since trying again
+/// later may work; a rejected request is the input's fault.
+fn tts_error(index: usize, e: TtsError) -> AppError {
+    let msg = format!("Chunk #{index} TTS error: {e}");
+    match e {
+        TtsError::InvalidInput(_) => AppError::InvalidInput(msg),
+        TtsError::RateLimited { .. } | TtsError::Upstream { .. } | TtsError::Network(_) => {
+            AppError::Unavailable(msg)
+        }
+        TtsError::Auth(_) => AppError::Internal(msg),
+    }
+}
+
+/// Upload the files built in `work_dir` to the job's output folder, record
+/// the outcome in the manifest and store it next to them, then drop the
+/// scratch copy. A failed upload fails the job; a failed manifest write is
+/// logged rather than failing a job whose audio was stored.
+async fn publish(
+    storage: &dyn Storage,
+    user_id: &str,
+    job_id: &str,
+    work_dir: &str,
+    manifest: &mut Manifest,
+    result: Result<&'static str, AppError>,
+) -> Result<GeneratedFile, AppError> {
+    let result = match result {
+        Ok(content_type) => upload_dir(storage, user_id, job_id, work_dir)
+            .await
+            .map(|_| content_type),
+        Err(e) => Err(e),
+    };
+
+    manifest.finish(&result);
+    let stored = match (
+        object_key(user_id, job_id, MANIFEST_FILE),
+        manifest.to_json(),
+    ) {
+        (Ok(manifest_key), Ok(json)) => storage
+            .put(&manifest_key, json, content_type_for(MANIFEST_FILE))
+            .await
+            .map_err(|e| format!("Failed to store {manifest_key}: {e}")),
+        (Err(e), _) => Err(format!("Failed to store {MANIFEST_FILE}: {e}")),
+        (_, Err(e)) => Err(e),
+    };
+    if let Err(e) = stored {
+        tracing::error!("{}", e);
+    }
+    if let Err(e) = fs::remove_dir_all(work_dir) {
+        tracing::error!("Failed to remove {}: {:?}", work_dir, e);
+    }
+
+    let content_type = result?;
+    Ok(GeneratedFile {
+        file_name: manifest.output.clone().unwrap_or_default(),
+        content_type,
+    })
+}
+
+/// Store every file in `work_dir` under `<user_id>/<job_id>/`. If any upload
+/// fails, the files already stored are removed again so a half-written
+/// folder never shows up in listings.
+async fn upload_dir(
+    storage: &dyn Storage,
+    user_id: &str,
+    job_id: &str,
+    work_dir: &str,
+) -> Result<(), AppError> {
+    let entries = fs::read_dir(work_dir)
+        .map_err(|e| AppError::Internal(format!("Failed to read {work_dir}: {e}")))?;
+    let mut files: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+    files.sort();
+    let files = files
+        .into_iter()
+        .map(|path| {
+            let name = manifest::file_name(&path.to_string_lossy());
+            Ok((object_key(user_id, job_id, &name)?, name, path))
+        })
+        .collect::<Result<Vec<_>, StorageError>>()?;
+
+    let mut stored: Vec<StoragePath> = Vec::new();
+    for (key, name, path) in files {
+        if let Err(e) = storage.put_file(&key, &path, content_type_for(&name)).await {
+            for stored_key in &stored {
+                if let Err(e) = storage.delete(stored_key).await {
+                    tracing::error!("Failed to remove {}: {}", stored_key, e);
+                }
+            }
+            return Err(AppError::Storage(format!("Failed to store {key}: {e}")));
+        }
+        info!("Stored {} in {} storage", key, storage.name());
+        stored.push(key);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::test_state;
+    use crate::services::jobs::JobSpec;
+    use crate::services::mock_tts::MockTts;
+    use crate::utils::audio_metadata::mp3_metadata;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    #[tokio::test]
+    async fn video_jobs_chunk_long_input_like_speech() {
+        let dir = std::env::temp_dir().join(format!("generation-{}", std::process::id()));
+        let state = test_state(&dir, b"unused");
+        let text = "Sentence number one is here. ".repeat(200);
+        assert!(text.len() > MAX_CHUNK_CHARS);
+        let job = state
+            .jobs
+            .submit(JobSpec::Video(SpeechRequest {
+                user_id: "alice".to_string(),
+                text,
+                voice: "alloy".to_string(),
+                options: TtsOptions::default(),
+            }))
+            .unwrap();
+
+        let mut updates = state.jobs.subscribe("alice", &job.id).unwrap();
+        updates
+            .wait_for(|job| job.status.is_finished())
+            .await
+            .unwrap();
+
+        // Encoding may fail without ffmpeg, but the audio went through the
+        // chunked pipeline either way.
+        let bytes = state
+            .storage
+            .get(&object_key("alice", &job.id, MANIFEST_FILE).unwrap())
+            .await
+            .unwrap();
+        let manifest = Manifest::from_json(&bytes).unwrap();
+        assert!(matches!(manifest.kind, JobKind::Video));
+        assert_eq!(manifest.chunks.len(), 2);
+        assert!(manifest.chunks.iter().all(|c| c.synthesis_ms.is_some()));
+        assert!(manifest.merge_ms.is_some());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn speech_jobs_merge_every_chunk_into_final_mp3() {
+        let dir = std::env::temp_dir().join(format!("generation-mp3-{}", std::process::id()));
+        let state = test_state(&dir, b"unused");
+        let text = "Sentence number one is here. ".repeat(200);
+        let job = state
+            .jobs
+            .submit(JobSpec::Speech(SpeechRequest {
+                user_id: "alice".to_string(),
+                text: text.clone(),
+                voice: "alloy".to_string(),
+                options: TtsOptions::default(),
+            }))
+            .unwrap();
+
+        let mut updates = state.jobs.subscribe("alice", &job.id).unwrap();
+        let finished = updates
+            .wait_for(|job| job.status.is_finished())
+            .await
+            .unwrap()
+            .clone();
+        assert!(matches!(finished.status, JobStatus::Done { .. }));
+
+        // The mock returns ceil(graphemes * 65ms / 24ms) frames per chunk;
+        // merging must keep every one of them and nothing else
+        let expected_frames: u64 = chunk_text_unicode(&text, MAX_CHUNK_CHARS)
+            .iter()
+            .map(|chunk| {
+                let ms = chunk.graphemes(true).count() as u64 * MockTts::DEFAULT_MS_PER_GRAPHEME;
+                ms.div_ceil(24)
+            })
+            .sum();
+        let mp3 = state
+            .storage
+            .get(&object_key("alice", &job.id, "final.mp3").unwrap())
+            .await
+            .unwrap();
+        let audio = mp3_metadata(&mp3).unwrap();
+        assert_eq!(audio.frame_count, expected_frames);
+        assert!((audio.duration_secs - expected_frames as f64 * 0.024).abs() < 1e-9);
+
+        let bytes = state
+            .storage
+            .get(&object_key("alice", &job.id, MANIFEST_FILE).unwrap())
+            .await
+            .unwrap();
+        let manifest = Manifest::from_json(&bytes).unwrap();
+        assert_eq!(manifest.chunks.len(), 2);
+        assert_eq!(manifest.audio.unwrap().frame_count, expected_frames);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn failed_jobs_carry_an_error_code() {
+        let dir = std::env::temp_dir().join(format!("generation-failed-{}", std::process::id()));
+        let state = test_state(&dir, b"unused");
+        let job = state
+            .jobs
+            .submit(JobSpec::Speech(SpeechRequest {
+                user_id: "alice".to_string(),
+                text: String::new(),
+                voice: "alloy".to_string(),
+                options: TtsOptions::default(),
+            }))
+            .unwrap();
+
+        let mut updates = state.jobs.subscribe("alice", &job.id).unwrap();
+        let finished = updates
+            .wait_for(|job| job.status.is_finished())
+            .await
+            .unwrap()
+            .clone();
+        let body = serde_json::to_value(&finished).unwrap();
+        assert_eq!(body["state"], "failed");
+        assert_eq!(body["code"], "invalid_input");
+        assert_eq!(body["error"], "No text provided.");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn long_input_needs_a_concatenable_format() {
+        let dir = std::env::temp_dir().join(format!("generation-wav-{}", std::process::id()));
+        let state = test_state(&dir, b"unused");
+        let job = state
+            .jobs
+            .submit(JobSpec::Speech(SpeechRequest {
+                user_id: "alice".to_string(),
+                text: "Sentence number one is here. ".repeat(200),
+                voice: "alloy".to_string(),
+                options: TtsOptions {
+                    response_format: AudioFormat::Wav,
+                    ..TtsOptions::default()
+                },
+            }))
+            .unwrap();
+
+        let mut updates = state.jobs.subscribe("alice", &job.id).unwrap();
+        let finished = updates
+            .wait_for(|job| job.status.is_finished())
+            .await
+            .unwrap()
+            .clone();
+        let body = serde_json::to_value(&finished).unwrap();
+        assert_eq!(body["state"], "failed");
+        assert_eq!(body["code"], "invalid_input");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
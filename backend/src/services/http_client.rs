@@ -0,0 +1,46 @@
+use reqwest::{Client, Proxy};
+use std::time::Duration;
+
+/// Settings for the outbound HTTP client shared by the TTS provider and the
+/// Clerk key fetches. S3 storage keeps its own client, since a total timeout
+/// would cut off long media streams.
+#[derive(Debug, Clone)]
+pub struct HttpClientConfig {
+    pub connect_timeout: Duration,
+    /// Limit for a whole request, including reading the response body.
+    pub timeout: Duration,
+    /// How often idle connections are pinged so they stay open.
+    pub keep_alive: Duration,
+    /// Proxy for every outbound request, e.g. `http://proxy.internal:3128`.
+    pub proxy: Option<String>,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            // Synthesizing a full 4096-character chunk can take a while
+            timeout: Duration::from_secs(120),
+            keep_alive: Duration::from_secs(30),
+            proxy: None,
+        }
+    }
+}
+
+/// Build the client. Clones share one connection pool, so build it once and
+/// hand out clones.
+pub fn build_client(config: &HttpClientConfig) -> Result<Client, String> {
+    let mut builder = Client::builder()
+        .connect_timeout(config.connect_timeout)
+        .timeout(config.timeout)
+        .tcp_keepalive(config.keep_alive)
+        .http2_keep_alive_interval(config.keep_alive)
+        .http2_keep_alive_while_idle(true);
+    if let Some(proxy) = &config.proxy {
+        let proxy = Proxy::all(proxy).map_err(|e| format!("Invalid proxy '{proxy}': {e}"))?;
+        builder = builder.proxy(proxy);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {e}"))
+}
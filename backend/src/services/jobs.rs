@@ -0,0 +1,263 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration as StdDuration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::error::AppError;
+use crate::services::generation::{self, GenerationContext, SpeechRequest};
+use crate::services::storage::media_url;
+use crate::utils::ulid::Ulid;
+
+/// Finished jobs are forgotten after this long.
+const FINISHED_JOB_TTL_HOURS: i64 = 1;
+/// How long a presigned `result_url` stays valid.
+const RESULT_URL_TTL: StdDuration = StdDuration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Speech,
+    Video,
+}
+
+/// Work handed to the worker pool.
+pub enum JobSpec {
+    Speech(SpeechRequest),
+    Video(SpeechRequest),
+}
+
+impl JobSpec {
+    fn kind(&self) -> JobKind {
+        match self {
+            JobSpec::Speech(_) => JobKind::Speech,
+            JobSpec::Video(_) => JobKind::Video,
+        }
+    }
+
+    fn request(&self) -> &SpeechRequest {
+        match self {
+            JobSpec::Speech(req) | JobSpec::Video(req) => req,
+        }
+    }
+}
+
+/// Where a job currently is in the pipeline.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    /// `chunk` of `total` chunks have been synthesized.
+    Synthesizing {
+        chunk: usize,
+        total: usize,
+    },
+    Merging,
+    /// `progress` is the encoded fraction of the audio, from 0.0 to 1.0.
+    Encoding {
+        progress: f64,
+    },
+    Done {
+        result_url: String,
+        content_type: String,
+    },
+    /// `code` is the stable error code an endpoint would answer with.
+    Failed {
+        error: String,
+        code: &'static str,
+    },
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Done { .. } | JobStatus::Failed { .. })
+    }
+}
+
+/// Snapshot of a job as returned by `GET /api/jobs/{id}`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: String,
+    /// Only the owner can see the job or its output.
+    #[serde(skip)]
+    pub user_id: String,
+    pub kind: JobKind,
+    /// Length of the input text, to estimate the output of unfinished jobs.
+    #[serde(skip)]
+    pub input_chars: usize,
+    #[serde(flatten)]
+    pub status: JobStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Each job lives in a `watch` channel so progress streams see every update.
+type JobTable = Arc<Mutex<HashMap<String, watch::Sender<Job>>>>;
+
+/// Lets the pipeline report progress for one job.
+#[derive(Clone)]
+pub struct JobHandle {
+    id: String,
+    jobs: JobTable,
+}
+
+impl JobHandle {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn set_status(&self, status: JobStatus) {
+        let jobs = self.jobs.lock().unwrap();
+        if let Some(tx) = jobs.get(&self.id) {
+            tx.send_modify(|job| {
+                job.status = status;
+                job.updated_at = Utc::now();
+            });
+        }
+    }
+}
+
+/// In-memory job table plus a fixed pool of workers draining a FIFO queue.
+pub struct JobQueue {
+    jobs: JobTable,
+    sender: UnboundedSender<(String, JobSpec)>,
+}
+
+impl JobQueue {
+    /// Spawn `workers` worker tasks on the current Tokio runtime.
+    pub fn start(workers: usize, ctx: GenerationContext) -> Self {
+        let jobs: JobTable = Arc::default();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+
+        for n in 1..=workers.max(1) {
+            tokio::spawn(worker(n, receiver.clone(), jobs.clone(), ctx.clone()));
+        }
+
+        Self { jobs, sender }
+    }
+
+    /// Record a new job as queued and hand it to the workers.
+    pub fn submit(&self, spec: JobSpec) -> Result<Job, AppError> {
+        let now = Utc::now();
+        let job = Job {
+            // Also the output folder name; see `generation::publish`.
+            id: Ulid::new().to_string(),
+            user_id: spec.request().user_id.clone(),
+            kind: spec.kind(),
+            input_chars: spec.request().text.chars().count(),
+            status: JobStatus::Queued,
+            created_at: now,
+            updated_at: now,
+        };
+
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            let cutoff = now - Duration::hours(FINISHED_JOB_TTL_HOURS);
+            jobs.retain(|_, tx| {
+                let j = tx.borrow();
+                !(j.status.is_finished() && j.updated_at < cutoff)
+            });
+            jobs.insert(job.id.clone(), watch::Sender::new(job.clone()));
+        }
+
+        self.sender
+            .send((job.id.clone(), spec))
+            .map_err(|_| AppError::Unavailable("Job workers are not running".to_string()))?;
+        info!("Queued {:?} job {}", job.kind, job.id);
+        Ok(job)
+    }
+
+    /// How many of `user_id`'s jobs are queued or running, and how many
+    /// characters of input they have between them.
+    pub fn pending(&self, user_id: &str) -> (usize, usize) {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.values()
+            .map(|tx| tx.borrow())
+            .filter(|job| job.user_id == user_id && !job.status.is_finished())
+            .fold((0, 0), |(count, chars), job| {
+                (count + 1, chars + job.input_chars)
+            })
+    }
+
+    /// The job `id` if it belongs to `user_id`.
+    pub fn get(&self, user_id: &str, id: &str) -> Option<Job> {
+        self.subscribe(user_id, id).map(|rx| rx.borrow().clone())
+    }
+
+    /// Receiver that yields the job's current snapshot and every later
+    /// change, if the job belongs to `user_id`.
+    pub fn subscribe(&self, user_id: &str, id: &str) -> Option<watch::Receiver<Job>> {
+        let jobs = self.jobs.lock().unwrap();
+        let tx = jobs.get(id)?;
+        let owned = tx.borrow().user_id == user_id;
+        owned.then(|| tx.subscribe())
+    }
+}
+
+async fn worker(
+    n: usize,
+    receiver: Arc<tokio::sync::Mutex<UnboundedReceiver<(String, JobSpec)>>>,
+    jobs: JobTable,
+    ctx: GenerationContext,
+) {
+    loop {
+        // Only one idle worker waits on the channel at a time.
+        let next = receiver.lock().await.recv().await;
+        let Some((id, spec)) = next else {
+            break;
+        };
+        info!("Worker #{} picked up job {}", n, id);
+
+        let handle = JobHandle {
+            id: id.clone(),
+            jobs: jobs.clone(),
+        };
+        let user_id = spec.request().user_id.clone();
+        let job_ctx = ctx.clone();
+        let job = handle.clone();
+        // Run in its own task so a panicking job can't take the worker down.
+        let result = tokio::spawn(async move {
+            match spec {
+                JobSpec::Speech(req) => generation::generate_speech(&job_ctx, &req, &job).await,
+                JobSpec::Video(req) => generation::generate_video(&job_ctx, &req, &job).await,
+            }
+        })
+        .await
+        .unwrap_or_else(|e| Err(AppError::Internal(format!("Job panicked: {e}"))));
+
+        let result = match result {
+            Ok(file) => media_url(
+                ctx.storage.as_ref(),
+                &user_id,
+                &id,
+                &file.file_name,
+                RESULT_URL_TTL,
+            )
+            .await
+            .map(|url| (file, url))
+            .map_err(|e| AppError::Storage(format!("Failed to build a URL for the output: {e}"))),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok((file, result_url)) => {
+                info!("Job {} finished: {}", id, file.file_name);
+                handle.set_status(JobStatus::Done {
+                    result_url,
+                    content_type: file.content_type.to_string(),
+                });
+            }
+            Err(e) => {
+                info!("Job {} failed: {}", id, e);
+                handle.set_status(JobStatus::Failed {
+                    error: e.to_string(),
+                    code: e.code(),
+                });
+            }
+        }
+    }
+}
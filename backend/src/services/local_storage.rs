@@ -0,0 +1,302 @@
+use async_trait::async_trait;
+use futures::stream;
+use std::io::{self, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::services::storage::{
+    ByteStream, ObjectInfo, ObjectMeta, Storage, StorageError, StoragePath,
+};
+use crate::utils::storage_path::resolve_key;
+
+/// Read size used when streaming a file.
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Objects stored as files under a local directory. There are no direct
+/// links; the app serves the files itself.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    /// Use `root` (created if missing) as the storage directory.
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        std::fs::create_dir_all(root.as_ref())?;
+        Ok(Self {
+            root: root.as_ref().canonicalize()?,
+        })
+    }
+
+    fn path(&self, key: &StoragePath) -> Result<PathBuf, StorageError> {
+        Ok(resolve_key(&self.root, key)?)
+    }
+
+    /// Remove directories left empty by a delete, stopping at the root.
+    async fn prune_empty_parents(&self, path: &Path) {
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            if d == self.root || fs::remove_dir(d).await.is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+}
+
+fn io_error(path: &Path, e: io::Error) -> StorageError {
+    match e.kind() {
+        io::ErrorKind::NotFound => StorageError::NotFound,
+        _ => StorageError::Backend(format!("{}: {e}", path.display())),
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(
+        &self,
+        key: &StoragePath,
+        data: Vec<u8>,
+        _content_type: &str,
+    ) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| io_error(parent, e))?;
+        }
+        fs::write(&path, data).await.map_err(|e| io_error(&path, e))
+    }
+
+    async fn put_file(
+        &self,
+        key: &StoragePath,
+        source: &Path,
+        _content_type: &str,
+    ) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| io_error(parent, e))?;
+        }
+        fs::copy(source, &path)
+            .await
+            .map(|_| ())
+            .map_err(|e| io_error(&path, e))
+    }
+
+    async fn get(&self, key: &StoragePath) -> Result<Vec<u8>, StorageError> {
+        let path = self.path(key)?;
+        fs::read(&path).await.map_err(|e| io_error(&path, e))
+    }
+
+    async fn head(&self, key: &StoragePath) -> Result<ObjectMeta, StorageError> {
+        let path = self.path(key)?;
+        let metadata = fs::metadata(&path).await.map_err(|e| io_error(&path, e))?;
+        if !metadata.is_file() {
+            return Err(StorageError::NotFound);
+        }
+        let last_modified = metadata.modified().ok();
+        // Size and mtime change whenever the file is rewritten.
+        let etag = last_modified
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|t| format!("{:x}-{:x}", metadata.len(), t.as_nanos()));
+        Ok(ObjectMeta {
+            size: metadata.len(),
+            last_modified,
+            etag,
+        })
+    }
+
+    async fn stream(
+        &self,
+        key: &StoragePath,
+        range: Option<Range<u64>>,
+    ) -> Result<ByteStream, StorageError> {
+        let path = self.path(key)?;
+        let mut file = fs::File::open(&path)
+            .await
+            .map_err(|e| io_error(&path, e))?;
+        let range = range.unwrap_or(0..u64::MAX);
+        file.seek(SeekFrom::Start(range.start))
+            .await
+            .map_err(|e| io_error(&path, e))?;
+
+        let reader = file.take(range.end - range.start);
+        Ok(Box::pin(stream::try_unfold(reader, move |mut reader| {
+            let path = path.clone();
+            async move {
+                let mut buf = vec![0; STREAM_CHUNK_SIZE];
+                let n = reader
+                    .read(&mut buf)
+                    .await
+                    .map_err(|e| io_error(&path, e))?;
+                if n == 0 {
+                    return Ok(None);
+                }
+                buf.truncate(n);
+                Ok(Some((buf, reader)))
+            }
+        })))
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, StorageError> {
+        // Walk the deepest directory the prefix names, then filter by prefix.
+        let dir_key = prefix.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let dir = if dir_key.is_empty() {
+            self.root.clone()
+        } else {
+            self.path(&StoragePath::parse(dir_key)?)?
+        };
+
+        let mut objects = Vec::new();
+        let mut pending = vec![(dir, dir_key.to_string())];
+        while let Some((dir, dir_key)) = pending.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(io_error(&dir, e)),
+            };
+            while let Some(entry) = entries.next_entry().await.map_err(|e| io_error(&dir, e))? {
+                let Ok(name) = entry.file_name().into_string() else {
+                    continue; // skip non-UTF8
+                };
+                let key = if dir_key.is_empty() {
+                    name
+                } else {
+                    format!("{dir_key}/{name}")
+                };
+                // `file_type` doesn't follow symlinks, so links are skipped.
+                let Ok(file_type) = entry.file_type().await else {
+                    continue;
+                };
+                if file_type.is_dir() {
+                    pending.push((entry.path(), key));
+                } else if file_type.is_file() && key.starts_with(prefix) {
+                    // Files with names that aren't valid keys can't be addressed
+                    let Ok(key) = StoragePath::parse(&key) else {
+                        continue;
+                    };
+                    let size = entry.metadata().await.map(|m| m.len()).unwrap_or(0);
+                    objects.push(ObjectInfo { key, size });
+                }
+            }
+        }
+
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn delete(&self, key: &StoragePath) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+        fs::remove_file(&path)
+            .await
+            .map_err(|e| io_error(&path, e))?;
+        self.prune_empty_parents(&path).await;
+        Ok(())
+    }
+
+    async fn presigned_url(
+        &self,
+        key: &StoragePath,
+        _expires_in: Duration,
+    ) -> Result<Option<String>, StorageError> {
+        self.path(key)?;
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+
+    fn key(s: &str) -> StoragePath {
+        s.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn round_trips_objects() {
+        let dir = std::env::temp_dir().join(format!("local-storage-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let storage = LocalStorage::new(&dir).unwrap();
+
+        storage
+            .put(&key("public/a/final.mp3"), b"abc".to_vec(), "audio/mpeg")
+            .await
+            .unwrap();
+        storage
+            .put(
+                &key("public/a/manifest.json"),
+                b"{}".to_vec(),
+                "application/json",
+            )
+            .await
+            .unwrap();
+        storage
+            .put(&key("public/b/final.mp3"), b"de".to_vec(), "audio/mpeg")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            storage.get(&key("public/a/final.mp3")).await.unwrap(),
+            b"abc"
+        );
+        let meta = storage.head(&key("public/a/final.mp3")).await.unwrap();
+        assert_eq!(meta.size, 3);
+        assert!(meta.etag.is_some() && meta.last_modified.is_some());
+        let part: Vec<u8> = storage
+            .stream(&key("public/a/final.mp3"), Some(1..3))
+            .await
+            .unwrap()
+            .try_concat()
+            .await
+            .unwrap();
+        assert_eq!(part, b"bc");
+        assert_eq!(
+            storage.head(&key("public/a")).await,
+            Err(StorageError::NotFound),
+            "folders aren't objects"
+        );
+        let keys: Vec<_> = storage
+            .list("public/a/")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|o| o.key.to_string())
+            .collect();
+        assert_eq!(keys, ["public/a/final.mp3", "public/a/manifest.json"]);
+        assert_eq!(storage.list("public/").await.unwrap().len(), 3);
+        assert_eq!(
+            storage
+                .presigned_url(&key("public/b/final.mp3"), Duration::ZERO)
+                .await
+                .unwrap(),
+            None
+        );
+
+        storage.delete(&key("public/b/final.mp3")).await.unwrap();
+        assert_eq!(
+            storage.get(&key("public/b/final.mp3")).await,
+            Err(StorageError::NotFound)
+        );
+        assert!(!dir.join("public/b").exists(), "empty folder is removed");
+        assert_eq!(
+            storage.delete(&key("public/b/final.mp3")).await,
+            Err(StorageError::NotFound)
+        );
+        assert!(
+            "public/../secret".parse::<StoragePath>().is_err(),
+            "keys outside the root can't be built"
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+use crate::services::generation::SpeechRequest;
+use crate::services::jobs::JobKind;
+use crate::services::tts_service::AudioFormat;
+use crate::utils::audio_metadata::AudioMetadata;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Record of how an output folder was produced, stored as `manifest.json`
+/// next to the generated files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub job_id: String,
+    pub kind: JobKind,
+    /// `TtsProvider::name()` of the backend that synthesized the audio.
+    pub provider: String,
+    pub request: ManifestRequest,
+    pub chunks: Vec<ManifestChunk>,
+    /// File name of the final output inside the folder, once written.
+    pub output: Option<String>,
+    /// Properties of the final MP3, so listings don't have to fetch it.
+    pub audio: Option<AudioMetadata>,
+    /// Name the user gave the output, if any.
+    pub title: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub merge_ms: Option<u64>,
+    pub encode_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// The user's request, after defaults were applied.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestRequest {
+    pub text: String,
+    pub voice: String,
+    pub model: Option<String>,
+    pub speed: Option<f32>,
+    pub instructions: Option<String>,
+    pub response_format: AudioFormat,
+}
+
+/// One TTS call. `start`/`end` are UTF-8 byte offsets into `request.text`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestChunk {
+    pub index: usize,
+    pub start: usize,
+    pub end: usize,
+    /// Chunk file name, once written. Chunk files are deleted after a
+    /// successful merge.
+    pub file: Option<String>,
+    pub synthesis_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl Manifest {
+    pub fn new(job_id: &str, kind: JobKind, provider: &str, req: &SpeechRequest) -> Self {
+        Self {
+            job_id: job_id.to_string(),
+            kind,
+            provider: provider.to_string(),
+            request: ManifestRequest {
+                text: req.text.clone(),
+                voice: req.voice.clone(),
+                model: req.options.model.clone(),
+                speed: req.options.speed,
+                instructions: req.options.instructions.clone(),
+                response_format: req.options.response_format,
+            },
+            chunks: Vec::new(),
+            output: None,
+            audio: None,
+            title: None,
+            started_at: Utc::now(),
+            finished_at: None,
+            merge_ms: None,
+            encode_ms: None,
+            error: None,
+        }
+    }
+
+    /// Add one chunk per entry of `chunks`, locating each in the request text.
+    /// Chunks must appear in order, as `chunk_text_unicode` returns them.
+    pub fn add_chunks(&mut self, chunks: &[String]) {
+        let mut pos = 0;
+        for (i, chunk) in chunks.iter().enumerate() {
+            let start = self.request.text[pos..]
+                .find(chunk.as_str())
+                .map_or(pos, |offset| pos + offset);
+            let end = start + chunk.len();
+            pos = end.min(self.request.text.len());
+            self.chunks.push(ManifestChunk {
+                index: i + 1,
+                start,
+                end,
+                file: None,
+                synthesis_ms: None,
+                error: None,
+            });
+        }
+    }
+
+    /// Stamp the finish time and record the outcome.
+    pub fn finish<T, E: fmt::Display>(&mut self, result: &Result<T, E>) {
+        self.finished_at = Some(Utc::now());
+        if let Err(e) = result {
+            self.error = Some(e.to_string());
+        }
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, String> {
+        serde_json::to_vec_pretty(self).map_err(|e| format!("Failed to serialize manifest: {e}"))
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(bytes).map_err(|e| format!("Invalid {MANIFEST_FILE}: {e}"))
+    }
+}
+
+/// File name component of `path`, for recording files relative to the folder.
+pub fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
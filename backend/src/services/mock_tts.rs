@@ -0,0 +1,132 @@
+use async_trait::async_trait;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::services::tts_service::{AudioFormat, TtsError, TtsOptions, TtsProvider};
+use crate::utils::audio_metadata::{PCM_SAMPLE_RATE, WAV_HEADER_LEN};
+
+/// MPEG-1 Layer III, 32 kbps, 48 kHz, mono, no CRC, no padding.
+const FRAME_HEADER: [u8; 4] = [0xFF, 0xFB, 0x14, 0xC0];
+/// 144 * 32_000 / 48_000 bytes per frame.
+const FRAME_LEN: usize = 96;
+/// 1152 samples at 48 kHz.
+const FRAME_MS: u64 = 24;
+
+/// Offline provider that returns silent MP3 (or PCM/WAV) audio.
+///
+/// Every MP3 frame carries zeroed side information, so decoders play it back
+/// as silence. The clip length grows with the number of graphemes in the input,
+/// which keeps chunk ordering and duration checks meaningful in tests.
+pub struct MockTts {
+    ms_per_grapheme: u64,
+}
+
+impl MockTts {
+    /// Roughly a normal speaking rate (~15 characters per second).
+    pub const DEFAULT_MS_PER_GRAPHEME: u64 = 65;
+
+    pub fn new() -> Self {
+        Self {
+            ms_per_grapheme: Self::DEFAULT_MS_PER_GRAPHEME,
+        }
+    }
+
+    fn duration_ms(&self, text: &str) -> u64 {
+        text.graphemes(true).count() as u64 * self.ms_per_grapheme
+    }
+
+    /// Number of frames generated for `text`; always at least one.
+    fn frame_count(&self, text: &str) -> usize {
+        self.duration_ms(text).div_ceil(FRAME_MS).max(1) as usize
+    }
+
+    fn silent_mp3(&self, text: &str) -> Vec<u8> {
+        let frames = self.frame_count(text);
+        let mut out = Vec::with_capacity(frames * FRAME_LEN);
+        for _ in 0..frames {
+            out.extend_from_slice(&FRAME_HEADER);
+            out.resize(out.len() + FRAME_LEN - FRAME_HEADER.len(), 0);
+        }
+        out
+    }
+
+    fn silent_pcm(&self, text: &str) -> Vec<u8> {
+        let samples = self.duration_ms(text) * u64::from(PCM_SAMPLE_RATE) / 1000;
+        vec![0; samples as usize * 2]
+    }
+
+    fn silent_wav(&self, text: &str) -> Vec<u8> {
+        let pcm = self.silent_pcm(text);
+        let data_len = pcm.len() as u32;
+        let mut out = Vec::with_capacity(WAV_HEADER_LEN as usize + pcm.len());
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(36 + data_len).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+        out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        out.extend_from_slice(&1u16.to_le_bytes()); // mono
+        out.extend_from_slice(&PCM_SAMPLE_RATE.to_le_bytes());
+        out.extend_from_slice(&(PCM_SAMPLE_RATE * 2).to_le_bytes()); // byte rate
+        out.extend_from_slice(&2u16.to_le_bytes()); // block align
+        out.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_len.to_le_bytes());
+        out.extend_from_slice(&pcm);
+        out
+    }
+}
+
+impl Default for MockTts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl TtsProvider for MockTts {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn synthesize(
+        &self,
+        text: &str,
+        _voice: &str,
+        options: &TtsOptions,
+    ) -> Result<Vec<u8>, TtsError> {
+        match options.response_format {
+            AudioFormat::Mp3 => Ok(self.silent_mp3(text)),
+            AudioFormat::Pcm => Ok(self.silent_pcm(text)),
+            AudioFormat::Wav => Ok(self.silent_wav(text)),
+            other => Err(TtsError::InvalidInput(format!(
+                "Mock TTS cannot produce {other} audio"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synth(text: &str) -> Vec<u8> {
+        futures::executor::block_on(MockTts::new().synthesize(text, "onyx", &TtsOptions::default()))
+            .unwrap()
+    }
+
+    #[test]
+    fn output_is_whole_frames_with_sync_words() {
+        let bytes = synth("Hello, world!");
+        assert_eq!(bytes.len() % FRAME_LEN, 0);
+        for frame in bytes.chunks(FRAME_LEN) {
+            assert_eq!(&frame[..4], &FRAME_HEADER);
+        }
+    }
+
+    #[test]
+    fn duration_scales_with_input_length() {
+        let short = synth("Hi.");
+        let long = synth(&"Hi. ".repeat(100));
+        assert!(long.len() > short.len() * 50);
+        assert_eq!(synth("").len(), FRAME_LEN);
+    }
+}
@@ -1 +1,14 @@
+pub mod auth;
+pub mod generation;
+pub mod http_client;
+pub mod jobs;
+pub mod local_storage;
+pub mod manifest;
+pub mod mock_tts;
+pub mod quota;
+pub mod retention;
+pub mod s3_storage;
+pub mod share_links;
+pub mod storage;
+pub mod tts_limiter;
 pub mod tts_service;
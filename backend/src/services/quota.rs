@@ -0,0 +1,322 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::services::manifest::{Manifest, MANIFEST_FILE};
+use crate::services::storage::{Storage, StorageError, StoragePath};
+
+/// Speaking rate used to estimate the audio an unfinished job will produce.
+const SPOKEN_CHARS_PER_SEC: f64 = 15.0;
+/// Size of that audio at 128 kbps.
+const ESTIMATED_BYTES_PER_SEC: f64 = 16_000.0;
+
+/// Per-user limits; `None` means unlimited.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QuotaLimits {
+    pub max_bytes: Option<u64>,
+    pub max_audio_secs: Option<f64>,
+}
+
+/// What a user's stored outputs, and the jobs still producing more, add up
+/// to.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Usage {
+    pub bytes: u64,
+    /// Total duration of the generated audio, from each run's manifest.
+    pub audio_secs: f64,
+    /// Output folders with a manifest.
+    pub runs: usize,
+    /// Queued and running jobs. Their estimated output is included in
+    /// `bytes` and `audio_secs`, so queuing many jobs at once can't get
+    /// around the limits.
+    pub pending_jobs: usize,
+}
+
+impl Usage {
+    /// Count `jobs` unfinished jobs with `chars` characters of input
+    /// between them, at the audio they are expected to produce.
+    pub fn add_pending(&mut self, jobs: usize, chars: usize) {
+        let secs = estimated_audio_secs(chars);
+        self.pending_jobs += jobs;
+        self.audio_secs += secs;
+        self.bytes += (secs * ESTIMATED_BYTES_PER_SEC) as u64;
+    }
+}
+
+/// Seconds of speech `chars` characters of input are expected to produce.
+fn estimated_audio_secs(chars: usize) -> f64 {
+    chars as f64 / SPOKEN_CHARS_PER_SEC
+}
+
+/// Duration of a run's audio: measured when the manifest has it, otherwise
+/// (opus, aac, flac) estimated from the input. Failed runs count nothing.
+fn run_audio_secs(manifest: &Manifest) -> f64 {
+    match (&manifest.audio, &manifest.output) {
+        (Some(audio), _) => audio.duration_secs,
+        (None, Some(_)) => estimated_audio_secs(manifest.request.text.chars().count()),
+        (None, None) => 0.0,
+    }
+}
+
+/// Which limit a user is over.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuotaError {
+    Bytes { used: u64, limit: u64 },
+    AudioSecs { used: f64, limit: f64 },
+}
+
+impl fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaError::Bytes { used, limit } => {
+                write!(f, "storage quota exceeded: {used} of {limit} bytes used")
+            }
+            QuotaError::AudioSecs { used, limit } => write!(
+                f,
+                "audio quota exceeded: {used:.0} of {limit:.0} seconds used"
+            ),
+        }
+    }
+}
+
+/// Works out per-user usage from storage and checks it against the limits.
+///
+/// Sizes come from a listing on every call. Durations are read from each
+/// run's manifest once and cached, since they never change.
+pub struct UsageTracker {
+    limits: QuotaLimits,
+    /// Manifest key -> audio duration.
+    durations: Mutex<HashMap<StoragePath, f64>>,
+}
+
+impl UsageTracker {
+    pub fn new(limits: QuotaLimits) -> Self {
+        Self {
+            limits,
+            durations: Mutex::default(),
+        }
+    }
+
+    pub fn limits(&self) -> &QuotaLimits {
+        &self.limits
+    }
+
+    /// Current usage of everything stored under `<user_id>/`.
+    pub async fn usage(&self, storage: &dyn Storage, user_id: &str) -> Result<Usage, StorageError> {
+        let prefix = format!("{user_id}/");
+        let objects = storage.list(&prefix).await?;
+
+        let mut usage = Usage::default();
+        let mut manifests = HashSet::new();
+        for object in &objects {
+            usage.bytes += object.size;
+            if object.key.as_str().ends_with(&format!("/{MANIFEST_FILE}")) {
+                usage.runs += 1;
+                manifests.insert(&object.key);
+            }
+        }
+
+        // Forget deleted runs, then read the manifests not seen yet
+        let missing: Vec<&StoragePath> = {
+            let mut durations = self.durations.lock().unwrap();
+            durations
+                .retain(|key, _| !key.as_str().starts_with(&prefix) || manifests.contains(key));
+            manifests
+                .iter()
+                .copied()
+                .filter(|key| !durations.contains_key(*key))
+                .collect()
+        };
+        for key in missing {
+            let duration = match storage.get(key).await {
+                Ok(bytes) => Manifest::from_json(&bytes).map_or(0.0, |m| run_audio_secs(&m)),
+                // Deleted since the listing
+                Err(StorageError::NotFound) => continue,
+                Err(e) => return Err(e),
+            };
+            self.durations.lock().unwrap().insert(key.clone(), duration);
+        }
+
+        let durations = self.durations.lock().unwrap();
+        usage.audio_secs = manifests.iter().filter_map(|key| durations.get(*key)).sum();
+        Ok(usage)
+    }
+
+    /// Whether `usage` is within the limits.
+    pub fn check(&self, usage: &Usage) -> Result<(), QuotaError> {
+        if let Some(limit) = self.limits.max_bytes {
+            if usage.bytes >= limit {
+                return Err(QuotaError::Bytes {
+                    used: usage.bytes,
+                    limit,
+                });
+            }
+        }
+        if let Some(limit) = self.limits.max_audio_secs {
+            if usage.audio_secs >= limit {
+                return Err(QuotaError::AudioSecs {
+                    used: usage.audio_secs,
+                    limit,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::generation::SpeechRequest;
+    use crate::services::jobs::JobKind;
+    use crate::services::local_storage::LocalStorage;
+    use crate::services::storage::object_key;
+    use crate::services::tts_service::TtsOptions;
+    use crate::utils::audio_metadata::AudioMetadata;
+
+    #[tokio::test]
+    async fn adds_up_sizes_and_durations() {
+        let dir = std::env::temp_dir().join(format!("quota-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let storage = LocalStorage::new(&dir).unwrap();
+        let request = SpeechRequest {
+            user_id: "alice".to_string(),
+            text: "Hi".to_string(),
+            voice: "alloy".to_string(),
+            options: TtsOptions::default(),
+        };
+        for (folder, secs) in [("a", 30.0), ("b", 45.0)] {
+            let mut manifest = Manifest::new(folder, JobKind::Speech, "mock", &request);
+            manifest.audio = Some(AudioMetadata {
+                duration_secs: secs,
+                size_bytes: 100,
+                bitrate_kbps: 128,
+                sample_rate: 24_000,
+                channels: 1,
+                frame_count: 0,
+            });
+            let json = manifest.to_json().unwrap();
+            storage
+                .put(
+                    &object_key("alice", folder, MANIFEST_FILE).unwrap(),
+                    json,
+                    "",
+                )
+                .await
+                .unwrap();
+            storage
+                .put(
+                    &object_key("alice", folder, "final.mp3").unwrap(),
+                    vec![0; 100],
+                    "",
+                )
+                .await
+                .unwrap();
+        }
+        storage
+            .put(
+                &object_key("bob", "c", "final.mp3").unwrap(),
+                vec![0; 1000],
+                "",
+            )
+            .await
+            .unwrap();
+
+        let tracker = UsageTracker::new(QuotaLimits {
+            max_bytes: None,
+            max_audio_secs: Some(60.0),
+        });
+        let usage = tracker.usage(&storage, "alice").await.unwrap();
+        assert_eq!(usage.runs, 2);
+        assert_eq!(usage.audio_secs, 75.0);
+        assert!(usage.bytes > 200);
+        assert!(matches!(
+            tracker.check(&usage),
+            Err(QuotaError::AudioSecs { .. })
+        ));
+
+        // Deleting a run frees its share
+        storage
+            .delete(&object_key("alice", "b", MANIFEST_FILE).unwrap())
+            .await
+            .unwrap();
+        storage
+            .delete(&object_key("alice", "b", "final.mp3").unwrap())
+            .await
+            .unwrap();
+        let usage = tracker.usage(&storage, "alice").await.unwrap();
+        assert_eq!(usage.audio_secs, 30.0);
+        assert_eq!(tracker.check(&usage), Ok(()));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn counts_runs_in_every_format() {
+        use crate::app_state::test_state;
+        use crate::services::jobs::JobSpec;
+        use crate::services::mock_tts::MockTts;
+        use crate::services::tts_service::AudioFormat;
+
+        let dir = std::env::temp_dir().join(format!("quota-formats-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let state = test_state(&dir, b"unused");
+
+        // A PCM run is measured from its size
+        let text = "Hello there, general.";
+        let job = state
+            .jobs
+            .submit(JobSpec::Speech(SpeechRequest {
+                user_id: "alice".to_string(),
+                text: text.to_string(),
+                voice: "alloy".to_string(),
+                options: TtsOptions {
+                    response_format: AudioFormat::Pcm,
+                    ..TtsOptions::default()
+                },
+            }))
+            .unwrap();
+        let mut updates = state.jobs.subscribe("alice", &job.id).unwrap();
+        updates
+            .wait_for(|job| job.status.is_finished())
+            .await
+            .unwrap();
+        let pcm_secs = (text.len() as u64 * MockTts::DEFAULT_MS_PER_GRAPHEME) as f64 / 1000.0;
+        let tracker = UsageTracker::new(QuotaLimits::default());
+        let usage = tracker
+            .usage(state.storage.as_ref(), "alice")
+            .await
+            .unwrap();
+        assert!((usage.audio_secs - pcm_secs).abs() < 1e-6);
+
+        // An opus run, which isn't measured, is estimated from its input
+        let request = SpeechRequest {
+            user_id: "alice".to_string(),
+            text: "x".repeat(150),
+            voice: "alloy".to_string(),
+            options: TtsOptions {
+                response_format: AudioFormat::Opus,
+                ..TtsOptions::default()
+            },
+        };
+        let mut manifest = Manifest::new("opus", JobKind::Speech, "openai", &request);
+        manifest.output = Some("final.opus".to_string());
+        state
+            .storage
+            .put(
+                &object_key("alice", "opus", MANIFEST_FILE).unwrap(),
+                manifest.to_json().unwrap(),
+                "",
+            )
+            .await
+            .unwrap();
+        let usage = tracker
+            .usage(state.storage.as_ref(), "alice")
+            .await
+            .unwrap();
+        assert!((usage.audio_secs - (pcm_secs + 10.0)).abs() < 1e-6);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
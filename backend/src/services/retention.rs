@@ -0,0 +1,242 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::services::storage::{Storage, StorageError, StoragePath};
+use crate::utils::folder_names::folder_created_at;
+
+/// Prefix of the per-chunk files merged into `final.*`.
+pub const CHUNK_FILE_PREFIX: &str = "speech-chunk-";
+
+/// How long outputs are kept. Chunk files next to a merged `final.*` are
+/// always removed.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RetentionPolicy {
+    /// Runs older than this are deleted.
+    pub max_age_days: Option<u32>,
+    /// Only this many of each user's newest runs are kept.
+    pub max_runs_per_user: Option<usize>,
+}
+
+/// What a cleanup pass deletes, or would delete when `dry_run` is set.
+#[derive(Debug, Default, Serialize)]
+pub struct CleanupReport {
+    pub dry_run: bool,
+    pub policy: RetentionPolicy,
+    /// Keys of leftover chunk files.
+    pub chunk_files: Vec<String>,
+    /// `<user_id>/<folder>` of runs older than `max_age_days`.
+    pub expired_runs: Vec<String>,
+    /// `<user_id>/<folder>` of runs beyond `max_runs_per_user`.
+    pub excess_runs: Vec<String>,
+    /// Bytes freed (or that would be).
+    pub bytes: u64,
+}
+
+/// One output folder as seen in a listing.
+#[derive(Default)]
+struct Run {
+    created_at: Option<DateTime<Utc>>,
+    merged: bool,
+    /// (key, size) of every object in the folder.
+    objects: Vec<(StoragePath, u64)>,
+}
+
+/// Work out what `policy` removes from the objects under `prefix` (`""` for
+/// every user, `"<user_id>/"` for one), and delete it unless `dry_run`.
+pub async fn cleanup(
+    storage: &dyn Storage,
+    policy: &RetentionPolicy,
+    prefix: &str,
+    dry_run: bool,
+    now: DateTime<Utc>,
+) -> Result<CleanupReport, StorageError> {
+    // 1) Group objects into runs per user
+    let mut users: BTreeMap<String, BTreeMap<String, Run>> = BTreeMap::new();
+    for object in storage.list(prefix).await? {
+        let mut parts = object.key.as_str().splitn(3, '/');
+        let (Some(user_id), Some(folder), Some(file)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        let run = users
+            .entry(user_id.to_string())
+            .or_default()
+            .entry(folder.to_string())
+            .or_insert_with(|| Run {
+                created_at: folder_created_at(folder),
+                ..Run::default()
+            });
+        run.merged |= file.starts_with("final.");
+        run.objects.push((object.key, object.size));
+    }
+
+    // 2) Pick expired and excess runs, newest first per user; folders
+    //    without a recognizable creation time are never purged
+    let mut report = CleanupReport {
+        dry_run,
+        policy: policy.clone(),
+        ..CleanupReport::default()
+    };
+    let cutoff = policy
+        .max_age_days
+        .map(|days| now - Duration::days(i64::from(days)));
+    let mut doomed = Vec::new();
+    for (user_id, runs) in &users {
+        let mut dated: Vec<_> = runs
+            .iter()
+            .filter_map(|(folder, run)| Some((run.created_at?, folder, run)))
+            .collect();
+        dated.sort_by(|a, b| (b.0, b.1).cmp(&(a.0, a.1)));
+
+        let mut kept = 0;
+        let mut purged = HashSet::new();
+        for (created_at, folder, run) in dated {
+            let name = format!("{user_id}/{folder}");
+            if cutoff.is_some_and(|cutoff| created_at < cutoff) {
+                report.expired_runs.push(name);
+            } else if policy.max_runs_per_user.is_some_and(|max| kept >= max) {
+                report.excess_runs.push(name);
+            } else {
+                kept += 1;
+                continue;
+            }
+            purged.insert(folder);
+            doomed.extend(run.objects.iter().cloned());
+        }
+
+        // 3) Chunk files left next to a merged output in the runs kept
+        let kept_runs = runs
+            .iter()
+            .filter(|(folder, run)| run.merged && !purged.contains(folder));
+        for (_, run) in kept_runs {
+            for (key, size) in &run.objects {
+                let is_chunk = key
+                    .segments()
+                    .last()
+                    .is_some_and(|file| file.starts_with(CHUNK_FILE_PREFIX));
+                if is_chunk {
+                    report.chunk_files.push(key.to_string());
+                    doomed.push((key.clone(), *size));
+                }
+            }
+        }
+    }
+    report.bytes = doomed.iter().map(|(_, size)| size).sum();
+
+    // 4) Delete, unless this is only a report
+    if !dry_run {
+        for (key, _) in &doomed {
+            match storage.delete(key).await {
+                Ok(()) | Err(StorageError::NotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Run [`cleanup`] over every user's files once per `interval`, starting
+/// right away.
+pub fn start(storage: Arc<dyn Storage>, policy: RetentionPolicy, interval: std::time::Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match cleanup(storage.as_ref(), &policy, "", false, Utc::now()).await {
+                Ok(report) => {
+                    let runs = report.expired_runs.len() + report.excess_runs.len();
+                    if runs > 0 || !report.chunk_files.is_empty() {
+                        info!(
+                            "Cleanup removed {} run(s) and {} chunk file(s), {} bytes",
+                            runs,
+                            report.chunk_files.len(),
+                            report.bytes
+                        );
+                    }
+                }
+                Err(e) => error!("Cleanup failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::local_storage::LocalStorage;
+    use crate::services::storage::object_key;
+    use crate::utils::ulid::Ulid;
+
+    #[tokio::test]
+    async fn purges_old_and_excess_runs_and_chunks() {
+        let dir = std::env::temp_dir().join(format!("retention-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let storage = LocalStorage::new(&dir).unwrap();
+        let now = Utc::now();
+        let folder = |days_ago: i64| {
+            let created = now - Duration::days(days_ago);
+            Ulid::from_timestamp_ms(created.timestamp_millis() as u64).to_string()
+        };
+        let (old, middle, new) = (folder(40), folder(5), folder(1));
+        for (user, folder) in [
+            ("alice", &old),
+            ("alice", &middle),
+            ("alice", &new),
+            ("bob", &old),
+        ] {
+            for file in ["final.mp3", "speech-chunk-1.mp3"] {
+                storage
+                    .put(&object_key(user, folder, file).unwrap(), vec![0; 10], "")
+                    .await
+                    .unwrap();
+            }
+        }
+        storage
+            .put(
+                &object_key("alice", "unnamed", "speech-chunk-1.mp3").unwrap(),
+                vec![0; 10],
+                "",
+            )
+            .await
+            .unwrap();
+        let policy = RetentionPolicy {
+            max_age_days: Some(30),
+            max_runs_per_user: Some(1),
+        };
+
+        // A dry run for one user only reports
+        let report = cleanup(&storage, &policy, "alice/", true, now)
+            .await
+            .unwrap();
+        assert_eq!(report.expired_runs, [format!("alice/{old}")]);
+        assert_eq!(report.excess_runs, [format!("alice/{middle}")]);
+        assert_eq!(
+            report.chunk_files,
+            [format!("alice/{new}/speech-chunk-1.mp3")]
+        );
+        assert_eq!(report.bytes, 50);
+        assert_eq!(storage.list("").await.unwrap().len(), 9);
+
+        let report = cleanup(&storage, &policy, "", false, now).await.unwrap();
+        assert_eq!(report.expired_runs.len(), 2);
+        let left: Vec<_> = storage
+            .list("")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|o| o.key.to_string())
+            .collect();
+        assert_eq!(
+            left,
+            [
+                format!("alice/{new}/final.mp3"),
+                "alice/unnamed/speech-chunk-1.mp3".to_string(),
+            ]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
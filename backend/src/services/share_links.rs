@@ -0,0 +1,206 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::services::tts_service::AudioFormat;
+
+/// Whether a share link can point at `file_name`: only a folder's final
+/// audio, in any format, or its MP4.
+pub fn is_shareable(file_name: &str) -> bool {
+    match file_name.strip_prefix("final.") {
+        Some("mp4") => true,
+        Some(ext) => ext.parse::<AudioFormat>().is_ok(),
+        None => false,
+    }
+}
+
+/// What a share link grants: one file of one user's output folder until
+/// `expires_at`, at most `max_downloads` times.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareGrant {
+    /// Identifies the link for download counting.
+    pub id: String,
+    pub user_id: String,
+    pub dir_name: String,
+    pub file_name: String,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub expires_at: DateTime<Utc>,
+    pub max_downloads: Option<u32>,
+}
+
+/// Why a share link was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShareError {
+    Malformed,
+    InvalidSignature,
+    Expired,
+    LimitReached,
+}
+
+impl fmt::Display for ShareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShareError::Malformed => f.write_str("malformed share link"),
+            ShareError::InvalidSignature => f.write_str("invalid share link signature"),
+            ShareError::Expired => f.write_str("share link has expired"),
+            ShareError::LimitReached => f.write_str("share link download limit reached"),
+        }
+    }
+}
+
+/// Mints and checks share tokens: `<base64url grant JSON>.<base64url
+/// HMAC-SHA256>`. Download counts are kept in memory, so limits restart from
+/// zero when the server does.
+pub struct ShareLinks {
+    key: hmac::Key,
+    /// Link id -> (bytes served so far, expiry) for links with a limit.
+    downloads: Mutex<HashMap<String, (u64, DateTime<Utc>)>>,
+}
+
+impl ShareLinks {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret),
+            downloads: Mutex::default(),
+        }
+    }
+
+    /// Sign with a random key. Links stop working when the server restarts.
+    pub fn with_random_key() -> Self {
+        let mut secret = [0u8; 32];
+        SystemRandom::new()
+            .fill(&mut secret)
+            .expect("system RNG is unavailable");
+        Self::new(&secret)
+    }
+
+    pub fn mint(&self, grant: &ShareGrant) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(grant).expect("grant serializes"));
+        let tag = hmac::sign(&self.key, payload.as_bytes());
+        format!("{payload}.{}", URL_SAFE_NO_PAD.encode(tag.as_ref()))
+    }
+
+    /// Check the signature and expiry of `token` and return its grant.
+    pub fn verify(&self, token: &str) -> Result<ShareGrant, ShareError> {
+        let (payload, signature) = token.split_once('.').ok_or(ShareError::Malformed)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| ShareError::Malformed)?;
+        hmac::verify(&self.key, payload.as_bytes(), &signature)
+            .map_err(|_| ShareError::InvalidSignature)?;
+
+        let json = URL_SAFE_NO_PAD
+            .decode(payload)
+            .map_err(|_| ShareError::Malformed)?;
+        let grant: ShareGrant = serde_json::from_slice(&json).map_err(|_| ShareError::Malformed)?;
+        if grant.expires_at <= Utc::now() {
+            return Err(ShareError::Expired);
+        }
+        Ok(grant)
+    }
+
+    /// Count `bytes` sent from a `size`-byte file against the grant's limit,
+    /// if it has one. A link may send `max_downloads` times the file's size
+    /// in total, so ranged requests count as the part of a download they are.
+    pub fn record_download(
+        &self,
+        grant: &ShareGrant,
+        bytes: u64,
+        size: u64,
+    ) -> Result<(), ShareError> {
+        let Some(max) = grant.max_downloads else {
+            return Ok(());
+        };
+        let now = Utc::now();
+        let mut downloads = self.downloads.lock().unwrap();
+        downloads.retain(|_, (_, expires_at)| *expires_at > now);
+
+        let (served, _) = downloads
+            .entry(grant.id.clone())
+            .or_insert((0, grant.expires_at));
+        if *served + bytes > u64::from(max).saturating_mul(size) {
+            return Err(ShareError::LimitReached);
+        }
+        *served += bytes;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn grant(expires_in: Duration, max_downloads: Option<u32>) -> ShareGrant {
+        ShareGrant {
+            id: "01JR0000000000000000000001".to_string(),
+            user_id: "user_1".to_string(),
+            dir_name: "01JR0000000000000000000000".to_string(),
+            file_name: "final.mp3".to_string(),
+            expires_at: Utc::now() + expires_in,
+            max_downloads,
+        }
+    }
+
+    #[test]
+    fn round_trips_and_rejects_tampering() {
+        let links = ShareLinks::new(b"secret");
+        let original = grant(Duration::hours(1), None);
+        let token = links.mint(&original);
+        let verified = links.verify(&token).unwrap();
+        assert_eq!(verified.dir_name, original.dir_name);
+        assert_eq!(
+            verified.expires_at.timestamp(),
+            original.expires_at.timestamp()
+        );
+
+        // Swap in another user's grant, keeping the old signature
+        let mut forged = original.clone();
+        forged.user_id = "user_2".to_string();
+        let forged_token = links.mint(&forged);
+        let (payload, _) = forged_token.split_once('.').unwrap();
+        let (_, signature) = token.split_once('.').unwrap();
+        assert_eq!(
+            links.verify(&format!("{payload}.{signature}")),
+            Err(ShareError::InvalidSignature)
+        );
+        assert_eq!(
+            ShareLinks::new(b"other").verify(&token),
+            Err(ShareError::InvalidSignature)
+        );
+        assert_eq!(links.verify("nonsense"), Err(ShareError::Malformed));
+    }
+
+    #[test]
+    fn expires() {
+        let links = ShareLinks::new(b"secret");
+        let token = links.mint(&grant(Duration::seconds(-1), None));
+        assert_eq!(links.verify(&token), Err(ShareError::Expired));
+    }
+
+    #[test]
+    fn enforces_download_limit() {
+        let links = ShareLinks::new(b"secret");
+        let limited = grant(Duration::hours(1), Some(2));
+        assert_eq!(links.record_download(&limited, 10, 10), Ok(()));
+        // Two halves make up the second download
+        assert_eq!(links.record_download(&limited, 5, 10), Ok(()));
+        assert_eq!(links.record_download(&limited, 5, 10), Ok(()));
+        assert_eq!(
+            links.record_download(&limited, 1, 10),
+            Err(ShareError::LimitReached)
+        );
+        // Revalidations send nothing and still go through
+        assert_eq!(links.record_download(&limited, 0, 10), Ok(()));
+
+        let unlimited = grant(Duration::hours(1), None);
+        for _ in 0..5 {
+            assert_eq!(links.record_download(&unlimited, 10, 10), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncWriteExt;
+
+pub use crate::utils::storage_path::StoragePath;
+use crate::utils::storage_path::StoragePathError;
+
+/// Why a storage operation failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    /// The key contains something other than plain name segments.
+    InvalidKey(String),
+    NotFound,
+    /// The key resolves outside the storage root.
+    Forbidden,
+    /// I/O or network failure talking to the backend.
+    Backend(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::InvalidKey(key) => write!(f, "invalid key '{key}'"),
+            StorageError::NotFound => f.write_str("not found"),
+            StorageError::Forbidden => f.write_str("path escapes the storage root"),
+            StorageError::Backend(e) => write!(f, "storage error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<StoragePathError> for StorageError {
+    fn from(e: StoragePathError) -> Self {
+        match e {
+            StoragePathError::Invalid(name) => StorageError::InvalidKey(name),
+            StoragePathError::Escapes => StorageError::Forbidden,
+        }
+    }
+}
+
+/// One stored object, as returned by [`Storage::list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectInfo {
+    pub key: StoragePath,
+    pub size: u64,
+}
+
+/// Size and cache validators of one object, as returned by [`Storage::head`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectMeta {
+    pub size: u64,
+    pub last_modified: Option<SystemTime>,
+    /// Opaque tag that changes whenever the content does, without quotes.
+    pub etag: Option<String>,
+}
+
+/// Object content in chunks, as returned by [`Storage::stream`].
+pub type ByteStream = BoxStream<'static, Result<Vec<u8>, StorageError>>;
+
+/// Where generated files live.
+///
+/// Objects are addressed by a [`StoragePath`], laid out as
+/// `<user_id>/<folder>/<file>` (see [`object_key`]). Listing prefixes are
+/// plain strings; they only filter, and local storage validates the
+/// directory they name.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Short identifier for logs.
+    fn name(&self) -> &'static str;
+
+    async fn put(
+        &self,
+        key: &StoragePath,
+        data: Vec<u8>,
+        content_type: &str,
+    ) -> Result<(), StorageError>;
+
+    /// Store the local file at `path` under `key`.
+    async fn put_file(
+        &self,
+        key: &StoragePath,
+        path: &Path,
+        content_type: &str,
+    ) -> Result<(), StorageError> {
+        let data = tokio::fs::read(path).await.map_err(|e| {
+            StorageError::Backend(format!("Failed to read {}: {e}", path.display()))
+        })?;
+        self.put(key, data, content_type).await
+    }
+
+    async fn get(&self, key: &StoragePath) -> Result<Vec<u8>, StorageError>;
+
+    async fn head(&self, key: &StoragePath) -> Result<ObjectMeta, StorageError>;
+
+    /// Stream the bytes in `range` of the object, or all of it if `None`.
+    /// The range must lie within the object's size.
+    async fn stream(
+        &self,
+        key: &StoragePath,
+        range: Option<Range<u64>>,
+    ) -> Result<ByteStream, StorageError>;
+
+    /// Every object whose key starts with `prefix`, sorted by key.
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, StorageError>;
+
+    /// Like [`list`](Self::list), but only keys that sort after
+    /// `start_after`. Backends that can skip the earlier keys server-side
+    /// should.
+    async fn list_after(
+        &self,
+        prefix: &str,
+        start_after: &str,
+    ) -> Result<Vec<ObjectInfo>, StorageError> {
+        let mut objects = self.list(prefix).await?;
+        objects.retain(|object| object.key.as_str() > start_after);
+        Ok(objects)
+    }
+
+    async fn delete(&self, key: &StoragePath) -> Result<(), StorageError>;
+
+    /// Presigned URL a browser can fetch the object from directly, valid for
+    /// `expires_in`. `None` if the backend has no such links; see
+    /// [`media_url`].
+    async fn presigned_url(
+        &self,
+        key: &StoragePath,
+        expires_in: Duration,
+    ) -> Result<Option<String>, StorageError>;
+}
+
+/// Route of the authorized handler that serves the caller's own files.
+pub const MEDIA_ROUTE: &str = "/api/media";
+
+/// URL for `file` in `user_id`'s folder `folder`: a presigned link when the
+/// backend supports them, otherwise the media handler, which only serves
+/// files to their owner.
+pub async fn media_url(
+    storage: &dyn Storage,
+    user_id: &str,
+    folder: &str,
+    file: &str,
+    expires_in: Duration,
+) -> Result<String, StorageError> {
+    let key = object_key(user_id, folder, file)?;
+    Ok(match storage.presigned_url(&key, expires_in).await? {
+        Some(url) => url,
+        None => format!("{MEDIA_ROUTE}/{folder}/{file}"),
+    })
+}
+
+/// Copy the object at `key` into the local file `path` one chunk at a time.
+pub async fn download_to_file(
+    storage: &dyn Storage,
+    key: &StoragePath,
+    path: &Path,
+) -> Result<(), StorageError> {
+    let write_error = |e| StorageError::Backend(format!("Failed to write {}: {e}", path.display()));
+    let mut body = storage.stream(key, None).await?;
+    let mut file = tokio::fs::File::create(path).await.map_err(write_error)?;
+    while let Some(chunk) = body.try_next().await? {
+        file.write_all(&chunk).await.map_err(write_error)?;
+    }
+    file.flush().await.map_err(write_error)
+}
+
+/// Key of `file` in the output folder `folder` of `user_id`. Fails with
+/// `InvalidKey` unless all three are plain names.
+pub fn object_key(user_id: &str, folder: &str, file: &str) -> Result<StoragePath, StorageError> {
+    Ok(StoragePath::parse(user_id)?.join(folder)?.join(file)?)
+}
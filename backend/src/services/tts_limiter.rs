@@ -0,0 +1,287 @@
+use async_trait::async_trait;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::services::tts_service::{TtsError, TtsOptions, TtsProvider};
+
+/// Limits on calls to the TTS provider, shared by every job.
+#[derive(Debug, Clone, Serialize)]
+pub struct TtsLimits {
+    /// Calls in flight at once.
+    pub max_concurrent: usize,
+    pub requests_per_min: Option<u32>,
+    /// Characters of input text sent per minute.
+    pub chars_per_min: Option<u32>,
+}
+
+impl Default for TtsLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 4,
+            requests_per_min: None,
+            chars_per_min: None,
+        }
+    }
+}
+
+/// Token bucket holding up to `capacity` tokens, refilled continuously.
+struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    /// Tokens available as of the instant.
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    /// A bucket allowing `per_min` tokens a minute, starting full.
+    fn per_minute(per_min: u32) -> Self {
+        Self::new(f64::from(per_min.max(1)), f64::from(per_min.max(1)) / 60.0)
+    }
+
+    fn new(capacity: f64, refill_per_sec: f64) -> Self {
+        Self {
+            capacity,
+            refill_per_sec,
+            state: Mutex::new((capacity, Instant::now())),
+        }
+    }
+
+    /// Take `n` tokens, waiting for them to refill if needed. Requests larger
+    /// than the bucket take all of it, so they are slowed but never stuck.
+    async fn acquire(&self, n: f64) {
+        let n = n.min(self.capacity);
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                let refilled = now.duration_since(state.1).as_secs_f64() * self.refill_per_sec;
+                *state = ((state.0 + refilled).min(self.capacity), now);
+                if state.0 >= n {
+                    state.0 -= n;
+                    return;
+                }
+                (n - state.0) / self.refill_per_sec
+            };
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+        }
+    }
+}
+
+/// Concurrency limit plus request and character rate limits for TTS calls.
+pub struct TtsLimiter {
+    limits: TtsLimits,
+    permits: Semaphore,
+    requests: Option<TokenBucket>,
+    chars: Option<TokenBucket>,
+}
+
+impl TtsLimiter {
+    pub fn new(limits: TtsLimits) -> Self {
+        Self {
+            permits: Semaphore::new(limits.max_concurrent.max(1)),
+            requests: limits.requests_per_min.map(TokenBucket::per_minute),
+            chars: limits.chars_per_min.map(TokenBucket::per_minute),
+            limits,
+        }
+    }
+
+    pub fn limits(&self) -> &TtsLimits {
+        &self.limits
+    }
+
+    /// Wait until a call with `chars` characters of input may start. The
+    /// call counts against the concurrency limit until the permit is dropped.
+    ///
+    /// The rate limits are waited out first, so a call sleeping on them
+    /// doesn't hold a permit that a call ready to run could use.
+    pub async fn acquire(&self, chars: usize) -> SemaphorePermit<'_> {
+        if let Some(requests) = &self.requests {
+            requests.acquire(1.0).await;
+        }
+        if let Some(bucket) = &self.chars {
+            bucket.acquire(chars as f64).await;
+        }
+        self.permits
+            .acquire()
+            .await
+            .expect("TTS semaphore is never closed")
+    }
+}
+
+/// A [`TtsProvider`] whose calls all go through one shared [`TtsLimiter`].
+pub struct LimitedTts {
+    inner: Arc<dyn TtsProvider>,
+    limiter: Arc<TtsLimiter>,
+}
+
+impl LimitedTts {
+    pub fn new(inner: Arc<dyn TtsProvider>, limiter: Arc<TtsLimiter>) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+#[async_trait]
+impl TtsProvider for LimitedTts {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn synthesize(
+        &self,
+        text: &str,
+        voice: &str,
+        options: &TtsOptions,
+    ) -> Result<Vec<u8>, TtsError> {
+        let _permit = self.limiter.acquire(text.chars().count()).await;
+        self.inner.synthesize(text, voice, options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::tts_service::{RetryPolicy, RetryingTts};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Provider that takes a while and records how many calls overlap.
+    #[derive(Default)]
+    struct SlowTts {
+        running: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TtsProvider for SlowTts {
+        fn name(&self) -> &'static str {
+            "slow"
+        }
+
+        async fn synthesize(&self, _: &str, _: &str, _: &TtsOptions) -> Result<Vec<u8>, TtsError> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn bounds_concurrent_calls() {
+        let slow = Arc::new(SlowTts::default());
+        let limiter = Arc::new(TtsLimiter::new(TtsLimits {
+            max_concurrent: 2,
+            ..TtsLimits::default()
+        }));
+        let tts = Arc::new(LimitedTts::new(slow.clone(), limiter));
+
+        let calls = (0..8).map(|_| {
+            let tts = tts.clone();
+            tokio::spawn(async move { tts.synthesize("hi", "alloy", &TtsOptions::default()).await })
+        });
+        for call in futures::future::join_all(calls).await {
+            call.unwrap().unwrap();
+        }
+        assert_eq!(slow.peak.load(Ordering::SeqCst), 2);
+    }
+
+    /// Provider whose first call for `"flaky"` is rate limited.
+    #[derive(Default)]
+    struct FlakyTts {
+        flaked: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TtsProvider for FlakyTts {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        async fn synthesize(
+            &self,
+            text: &str,
+            _: &str,
+            _: &TtsOptions,
+        ) -> Result<Vec<u8>, TtsError> {
+            if text == "flaky" && self.flaked.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(TtsError::RateLimited {
+                    retry_after: Some(Duration::from_millis(200)),
+                    message: "slow down".into(),
+                });
+            }
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_are_limited_per_attempt() {
+        let limiter = Arc::new(TtsLimiter::new(TtsLimits {
+            max_concurrent: 1,
+            requests_per_min: Some(3),
+            chars_per_min: None,
+        }));
+        let limited = Arc::new(LimitedTts::new(
+            Arc::new(FlakyTts::default()),
+            limiter.clone(),
+        ));
+        let tts = Arc::new(RetryingTts::new(limited, RetryPolicy::default()));
+
+        let flaky = tokio::spawn({
+            let tts = tts.clone();
+            async move {
+                tts.synthesize("flaky", "alloy", &TtsOptions::default())
+                    .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // The only permit is free while the flaky call backs off
+        tokio::time::timeout(
+            Duration::from_millis(100),
+            tts.synthesize("steady", "alloy", &TtsOptions::default()),
+        )
+        .await
+        .expect("permit held during backoff")
+        .unwrap();
+        flaky.await.unwrap().unwrap();
+
+        // The 429 and its retry both took a request token, so the bucket of
+        // 3 is empty after three attempts
+        let next = tokio::time::timeout(Duration::from_millis(50), limiter.acquire(0)).await;
+        assert!(next.is_err());
+    }
+
+    #[tokio::test]
+    async fn waits_for_tokens_before_taking_a_permit() {
+        let limiter = Arc::new(TtsLimiter::new(TtsLimits {
+            max_concurrent: 1,
+            requests_per_min: None,
+            chars_per_min: Some(60),
+        }));
+        drop(limiter.acquire(60).await);
+
+        // 30 more characters take 30s to refill; the permit stays free
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { drop(limiter.acquire(30).await) }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(limiter.permits.try_acquire().is_ok());
+        waiting.abort();
+    }
+
+    #[tokio::test]
+    async fn token_bucket_waits_for_refill() {
+        let bucket = TokenBucket::new(10.0, 100.0);
+        let started = Instant::now();
+        bucket.acquire(10.0).await;
+
+        // Empty now: 5 more tokens take ~50ms at 100/s
+        bucket.acquire(5.0).await;
+        assert!(started.elapsed() >= Duration::from_millis(45));
+
+        // Oversized requests drain the bucket instead of waiting forever
+        bucket.acquire(1000.0).await;
+    }
+}
@@ -1,83 +1,537 @@
-use reqwest::Client;
-use serde::Serialize;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{header::RETRY_AFTER, Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+pub const SUPPORTED_MODELS: &[&str] = &["tts-1", "tts-1-hd", "gpt-4o-mini-tts"];
+pub const SUPPORTED_VOICES: &[&str] = &[
+    "alloy", "ash", "ballad", "coral", "echo", "fable", "nova", "onyx", "sage", "shimmer", "verse",
+];
+pub const DEFAULT_VOICE: &str = "onyx";
+/// Only this model accepts free-form `instructions`.
+const INSTRUCTIONS_MODEL: &str = "gpt-4o-mini-tts";
+const SPEED_RANGE: std::ops::RangeInclusive<f32> = 0.25..=4.0;
+
+/// Audio container/codec returned by a provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    #[default]
+    Mp3,
+    Opus,
+    Aac,
+    Flac,
+    Wav,
+    Pcm,
+}
+
+impl AudioFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "audio/mpeg",
+            AudioFormat::Opus => "audio/ogg",
+            AudioFormat::Aac => "audio/aac",
+            AudioFormat::Flac => "audio/flac",
+            AudioFormat::Wav => "audio/wav",
+            AudioFormat::Pcm => "audio/pcm",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Opus => "opus",
+            AudioFormat::Aac => "aac",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Wav => "wav",
+            AudioFormat::Pcm => "pcm",
+        }
+    }
+
+    /// Whether chunk outputs can be joined by appending their bytes.
+    /// Formats with a single container header (wav, flac, ogg) cannot.
+    pub fn is_concatenable(self) -> bool {
+        matches!(self, AudioFormat::Mp3 | AudioFormat::Aac | AudioFormat::Pcm)
+    }
+}
+
+impl fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl FromStr for AudioFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mp3" => Ok(AudioFormat::Mp3),
+            "opus" => Ok(AudioFormat::Opus),
+            "aac" => Ok(AudioFormat::Aac),
+            "flac" => Ok(AudioFormat::Flac),
+            "wav" => Ok(AudioFormat::Wav),
+            "pcm" => Ok(AudioFormat::Pcm),
+            other => Err(format!(
+                "Unsupported response_format '{other}' (expected one of mp3, opus, aac, flac, wav, pcm)"
+            )),
+        }
+    }
+}
+
+/// Extra knobs passed to a provider alongside the text and voice.
+/// Providers fall back to their own defaults for anything left as `None`.
+#[derive(Debug, Clone, Default)]
+pub struct TtsOptions {
+    pub model: Option<String>,
+    pub speed: Option<f32>,
+    pub instructions: Option<String>,
+    pub response_format: AudioFormat,
+}
+
+impl TtsOptions {
+    /// Check `voice` and every option against the supported values.
+    pub fn validate(&self, voice: &str) -> Result<(), String> {
+        if !SUPPORTED_VOICES.contains(&voice) {
+            return Err(format!(
+                "Unsupported voice '{voice}' (expected one of {})",
+                SUPPORTED_VOICES.join(", ")
+            ));
+        }
+        if let Some(model) = &self.model {
+            if !SUPPORTED_MODELS.contains(&model.as_str()) {
+                return Err(format!(
+                    "Unsupported model '{model}' (expected one of {})",
+                    SUPPORTED_MODELS.join(", ")
+                ));
+            }
+        }
+        if let Some(speed) = self.speed {
+            if !SPEED_RANGE.contains(&speed) {
+                return Err(format!(
+                    "speed must be between {} and {}",
+                    SPEED_RANGE.start(),
+                    SPEED_RANGE.end()
+                ));
+            }
+        }
+        if self.instructions.is_some() && self.model.as_deref() != Some(INSTRUCTIONS_MODEL) {
+            return Err(format!("instructions require model '{INSTRUCTIONS_MODEL}'"));
+        }
+        Ok(())
+    }
+}
+
+/// Why a TTS call failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TtsError {
+    /// The provider rejected the credentials (401/403).
+    Auth(String),
+    /// Too many requests (429). `retry_after` is the provider's hint.
+    RateLimited {
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    /// The request can never succeed as sent (any other 4xx).
+    InvalidInput(String),
+    /// The provider failed or timed out (5xx, 408).
+    Upstream {
+        status: u16,
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    /// The provider could not be reached, or the response was cut off.
+    Network(String),
+}
+
+impl TtsError {
+    /// Map an unsuccessful HTTP response to an error.
+    fn from_response(status: StatusCode, retry_after: Option<Duration>, body: String) -> Self {
+        let message = format!("{status} - {body}");
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => TtsError::Auth(message),
+            StatusCode::TOO_MANY_REQUESTS => TtsError::RateLimited {
+                retry_after,
+                message,
+            },
+            StatusCode::REQUEST_TIMEOUT => TtsError::Upstream {
+                status: status.as_u16(),
+                retry_after,
+                message,
+            },
+            _ if status.is_client_error() => TtsError::InvalidInput(message),
+            _ => TtsError::Upstream {
+                status: status.as_u16(),
+                retry_after,
+                message,
+            },
+        }
+    }
+
+    /// Whether the same request may succeed if sent again.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            TtsError::RateLimited { .. } | TtsError::Upstream { .. } | TtsError::Network(_)
+        )
+    }
+
+    /// How long the provider asked us to wait before retrying.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            TtsError::RateLimited { retry_after, .. } | TtsError::Upstream { retry_after, .. } => {
+                *retry_after
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TtsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TtsError::Auth(msg) => write!(f, "TTS authentication failed: {msg}"),
+            TtsError::RateLimited { message, .. } => write!(f, "TTS rate limited: {message}"),
+            TtsError::InvalidInput(msg) => write!(f, "TTS rejected the request: {msg}"),
+            TtsError::Upstream { message, .. } => write!(f, "TTS provider error: {message}"),
+            TtsError::Network(msg) => write!(f, "TTS request failed: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for TtsError {}
+
+/// How failed TTS calls are retried: exponential backoff with jitter, or
+/// the provider's `Retry-After` when it sends one.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying.
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    /// Upper bound for any single wait, including `Retry-After`.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `retry` (0-based). Without a hint the
+    /// backoff doubles each time and a random half of it is jittered away,
+    /// so parallel chunks don't retry in lockstep.
+    pub fn delay(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(hint) = retry_after {
+            return hint.min(self.max_delay);
+        }
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let half = backoff / 2;
+        half + half.mul_f64(rand::rng().random::<f64>())
+    }
+}
+
+/// Parse a `Retry-After` value: delay seconds, or an HTTP date.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    if let Ok(secs) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    Some((at.with_timezone(&Utc) - now).to_std().unwrap_or_default())
+}
+
+/// A text-to-speech backend.
+///
+/// Handlers only ever talk to this trait (through `AppState`), so the vendor
+/// can be swapped without touching the endpoints.
+#[async_trait]
+pub trait TtsProvider: Send + Sync {
+    /// Short identifier used in logs, e.g. `"openai"`.
+    fn name(&self) -> &'static str;
+
+    /// Synthesize `text` with `voice` and return the encoded audio bytes.
+    async fn synthesize(
+        &self,
+        text: &str,
+        voice: &str,
+        options: &TtsOptions,
+    ) -> Result<Vec<u8>, TtsError>;
+}
 
 #[derive(Serialize)]
-struct TtsRequest {
-    model: String,
-    input: String,
-    voice: String,
-}
-
-pub async fn call_openai_tts(input_text: &str, voice: &str) -> Result<Vec<u8>, String> {
-    let client = Client::new();
-
-    // 1) Read API key
-    let api_key = std::env::var("OPENAI_API_KEY")
-        .map_err(|_| "Missing OPENAI_API_KEY environment variable".to_string())?;
-
-    // 2) Prepare request payload
-    let body = TtsRequest {
-        model: "tts-1".to_string(),
-        input: input_text.to_string(),
-        voice: voice.to_string(),
-    };
-
-    // 3) Make up to 2 attempts total
-    for attempt in 1..=2 {
-        // Make the HTTP request
-        let resp = match client
-            .post("https://api.openai.com/v1/audio/speech")
-            .bearer_auth(&api_key)
+struct TtsRequest<'a> {
+    model: &'a str,
+    input: &'a str,
+    voice: &'a str,
+    response_format: AudioFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    speed: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<&'a str>,
+}
+
+/// OpenAI `/v1/audio/speech` backend. Each call is a single request; wrap it
+/// in [`RetryingTts`] to retry transient failures.
+pub struct OpenAiTts {
+    client: Client,
+    api_key: String,
+    /// `<base_url>/audio/speech`.
+    endpoint: String,
+}
+
+impl OpenAiTts {
+    pub const DEFAULT_MODEL: &'static str = "tts-1";
+    pub const DEFAULT_BASE_URL: &'static str = "https://api.openai.com/v1";
+
+    /// `base_url` is normally [`Self::DEFAULT_BASE_URL`]; point it elsewhere
+    /// for an OpenAI-compatible gateway or a local mock server.
+    pub fn new(client: Client, api_key: impl Into<String>, base_url: &str) -> Self {
+        Self {
+            client,
+            api_key: api_key.into(),
+            endpoint: format!("{}/audio/speech", base_url.trim_end_matches('/')),
+        }
+    }
+}
+
+#[async_trait]
+impl TtsProvider for OpenAiTts {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    async fn synthesize(
+        &self,
+        text: &str,
+        voice: &str,
+        options: &TtsOptions,
+    ) -> Result<Vec<u8>, TtsError> {
+        // 1) Prepare request payload
+        let body = TtsRequest {
+            model: options.model.as_deref().unwrap_or(Self::DEFAULT_MODEL),
+            input: text,
+            voice,
+            response_format: options.response_format,
+            speed: options.speed,
+            instructions: options.instructions.as_deref(),
+        };
+
+        // 2) Send it
+        let resp = self
+            .client
+            .post(&self.endpoint)
+            .bearer_auth(&self.api_key)
             .json(&body)
             .send()
             .await
-        {
-            Ok(r) => r,
-            Err(e) => {
-                let err_msg = format!("Request error (attempt #{attempt}): {e}");
-                // If this is the second attempt, return the error; else retry.
-                if attempt == 2 {
-                    return Err(err_msg);
-                } else {
-                    eprintln!("{err_msg} — retrying...");
-                    continue;
-                }
-            }
-        };
+            .map_err(|e| TtsError::Network(e.to_string()))?;
 
-        // If the response is not success, read the text to see the error
-        if !resp.status().is_success() {
-            let status = resp.status();
+        // 3) Classify failures so the caller can decide whether to retry
+        let status = resp.status();
+        if !status.is_success() {
+            let retry_after = resp
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| parse_retry_after(v, Utc::now()));
             let text = resp.text().await.unwrap_or_default();
-            let err_msg = format!("TTS request failed (attempt #{attempt}): {status} - {text}");
-
-            if attempt == 2 {
-                // Return the error if it fails again
-                return Err(err_msg);
-            } else {
-                eprintln!("{err_msg} — retrying...");
-                continue;
-            }
+            return Err(TtsError::from_response(status, retry_after, text));
         }
 
-        // If we get a success status, attempt to parse the bytes
-        match resp.bytes().await {
-            Ok(bytes) => {
-                // Return the MP3 bytes on success
-                return Ok(bytes.to_vec());
-            }
-            Err(e) => {
-                let err_msg =
-                    format!("Unable to read TTS response bytes (attempt #{attempt}): {e}");
-                if attempt == 2 {
-                    return Err(err_msg);
-                } else {
-                    eprintln!("{err_msg} — retrying...");
-                }
+        let bytes = resp
+            .bytes()
+            .await
+            .map_err(|e| TtsError::Network(format!("unable to read response: {e}")))?;
+        Ok(bytes.to_vec())
+    }
+}
+
+/// Retries transient failures of the wrapped provider according to a
+/// [`RetryPolicy`]. Put it outside [`LimitedTts`](super::tts_limiter::LimitedTts)
+/// so every attempt is rate limited and no permit is held while backing off.
+pub struct RetryingTts {
+    inner: Arc<dyn TtsProvider>,
+    retry: RetryPolicy,
+}
+
+impl RetryingTts {
+    pub fn new(inner: Arc<dyn TtsProvider>, retry: RetryPolicy) -> Self {
+        Self { inner, retry }
+    }
+}
+
+#[async_trait]
+impl TtsProvider for RetryingTts {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn synthesize(
+        &self,
+        text: &str,
+        voice: &str,
+        options: &TtsOptions,
+    ) -> Result<Vec<u8>, TtsError> {
+        // Auth and input errors fail right away
+        let mut retry = 0;
+        loop {
+            let err = match self.inner.synthesize(text, voice, options).await {
+                Ok(bytes) => return Ok(bytes),
+                Err(e) => e,
+            };
+            if !err.is_retryable() || retry >= self.retry.max_retries {
+                return Err(err);
             }
+            let delay = self.retry.delay(retry, err.retry_after());
+            retry += 1;
+            warn!("{} (attempt #{}), retrying in {:?}", err, retry, delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn classifies_responses() {
+        let error = |status| TtsError::from_response(status, None, String::new());
+        assert!(matches!(error(StatusCode::UNAUTHORIZED), TtsError::Auth(_)));
+        assert!(matches!(
+            error(StatusCode::BAD_REQUEST),
+            TtsError::InvalidInput(_)
+        ));
+        assert!(matches!(
+            error(StatusCode::TOO_MANY_REQUESTS),
+            TtsError::RateLimited { .. }
+        ));
+        assert!(matches!(
+            error(StatusCode::SERVICE_UNAVAILABLE),
+            TtsError::Upstream { status: 503, .. }
+        ));
+
+        assert!(!error(StatusCode::FORBIDDEN).is_retryable());
+        assert!(!error(StatusCode::UNPROCESSABLE_ENTITY).is_retryable());
+        assert!(error(StatusCode::REQUEST_TIMEOUT).is_retryable());
+        assert!(error(StatusCode::BAD_GATEWAY).is_retryable());
+        assert!(TtsError::Network("reset".into()).is_retryable());
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn backs_off_exponentially_with_jitter() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        for (retry, full) in [(0, 100), (1, 200), (2, 400), (3, 800), (4, 1000)] {
+            let delay = policy.delay(retry, None);
+            let full = Duration::from_millis(full);
+            assert!(delay >= full / 2 && delay <= full, "{retry}: {delay:?}");
+        }
+
+        // Retry-After wins, up to the cap
+        let hint = Some(Duration::from_millis(300));
+        assert_eq!(policy.delay(0, hint), Duration::from_millis(300));
+        let hint = Some(Duration::from_secs(60));
+        assert_eq!(policy.delay(0, hint), Duration::from_secs(1));
+    }
+
+    /// Stand-in for `/v1/audio/speech`: the input text picks the behavior.
+    async fn fake_openai(
+        req: HttpRequest,
+        body: web::Json<serde_json::Value>,
+        calls: web::Data<AtomicUsize>,
+    ) -> HttpResponse {
+        let call = calls.fetch_add(1, Ordering::SeqCst);
+        if req.path() != "/v1/audio/speech" {
+            return HttpResponse::NotFound().finish();
+        }
+        match body["input"].as_str() {
+            Some("flaky") if call == 0 => HttpResponse::ServiceUnavailable()
+                .insert_header(("retry-after", "0"))
+                .body("overloaded"),
+            Some("bad") => HttpResponse::BadRequest().body("bad input"),
+            _ => HttpResponse::Ok().body("audio"),
         }
     }
 
-    // Fallback if something unexpected happens outside the loop
-    Err("Unexpected error: call_openai_tts ran out of attempts".to_string())
+    #[actix_web::test]
+    async fn retries_transient_errors_against_base_url() {
+        let calls = web::Data::new(AtomicUsize::new(0));
+        let app_calls = calls.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_calls.clone())
+                .default_service(web::route().to(fake_openai))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let openai = OpenAiTts::new(Client::new(), "sk-test", &format!("http://{addr}/v1/"));
+        let tts = RetryingTts::new(
+            Arc::new(openai),
+            RetryPolicy {
+                max_retries: 2,
+                base_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(10),
+            },
+        );
+        let options = TtsOptions::default();
+
+        assert_eq!(
+            tts.synthesize("flaky", "onyx", &options).await.unwrap(),
+            b"audio"
+        );
+        assert_eq!(calls.swap(0, Ordering::SeqCst), 2);
+
+        // Client errors are returned without retrying
+        let err = tts.synthesize("bad", "onyx", &options).await.unwrap_err();
+        assert!(matches!(err, TtsError::InvalidInput(_)), "{err}");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        handle.stop(false).await;
+    }
 }
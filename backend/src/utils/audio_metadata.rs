@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::utils::mp3::{self, FrameHeader};
+
+/// Enough bytes after any ID3v2 tag to hold the first frame, including a
+/// Xing/Info header at the highest bitrates.
+const HEAD_LEN: usize = 4096;
+
+/// How much of a stored MP3 to fetch for [`mp3_metadata_from_prefix`]: room
+/// for a modest ID3v2 tag plus the first frame.
+pub const PREFIX_LEN: u64 = 64 * 1024;
+
+/// Raw PCM as OpenAI returns it: 24 kHz, signed 16-bit little-endian, mono.
+pub const PCM_SAMPLE_RATE: u32 = 24_000;
+/// Bytes of RIFF header in front of the samples in a WAV file.
+pub const WAV_HEADER_LEN: u64 = 44;
+
+/// Duration and stream properties of the generated audio. MP3s are read from
+/// their frame headers without decoding; PCM and WAV from their size.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioMetadata {
+    pub duration_secs: f64,
+    pub size_bytes: u64,
+    /// Average over the whole file.
+    pub bitrate_kbps: u32,
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub frame_count: u64,
+}
+
+/// Read metadata for the MP3 at `path`.
+///
+/// Files that start with a Xing/Info frame (everything `concat_mp3` writes)
+/// only need their first few kilobytes read; anything else is scanned frame
+/// by frame.
+pub fn read_mp3_metadata(path: &str) -> std::io::Result<AudioMetadata> {
+    let mut file = File::open(path)?;
+    let size_bytes = file.metadata()?.len();
+
+    // 1) Skip any ID3v2 tag and look at the first frame.
+    let mut id3 = [0u8; 10];
+    let id3_read = read_up_to(&mut file, &mut id3)?;
+    let audio_start = mp3::id3v2_len(&id3[..id3_read]) as u64;
+    file.seek(SeekFrom::Start(audio_start))?;
+    let mut head = vec![0u8; HEAD_LEN];
+    let head_read = read_up_to(&mut file, &mut head)?;
+    head.truncate(head_read);
+
+    if let Some((header, frame)) = mp3::frames(&head).next() {
+        if let Some(frames) = mp3::xing_frame_count(frame, &header) {
+            let audio_bytes = size_bytes.saturating_sub(audio_start);
+            return Ok(summarize(
+                &header,
+                u64::from(frames),
+                audio_bytes,
+                size_bytes,
+            ));
+        }
+    }
+
+    // 2) No Xing frame: count every frame.
+    let data = std::fs::read(path)?;
+    mp3_metadata(&data).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("no MP3 frames found in {path}"),
+        )
+    })
+}
+
+/// Metadata for an in-memory MP3 by walking every frame.
+pub fn mp3_metadata(data: &[u8]) -> Option<AudioMetadata> {
+    let mut first: Option<FrameHeader> = None;
+    let mut frame_count = 0u64;
+    let mut audio_bytes = 0u64;
+
+    for (header, frame) in mp3::frames(mp3::strip_tags(data)) {
+        if mp3::is_vbr_header_frame(frame, &header) {
+            continue;
+        }
+        first.get_or_insert(header);
+        frame_count += 1;
+        audio_bytes += frame.len() as u64;
+    }
+
+    Some(summarize(
+        &first?,
+        frame_count,
+        audio_bytes,
+        data.len() as u64,
+    ))
+}
+
+/// Metadata for an MP3 of `size_bytes` from its first bytes only. Exact when
+/// the file starts with a Xing/Info frame; otherwise the duration is
+/// estimated from the first frame, as if the bitrate were constant.
+pub fn mp3_metadata_from_prefix(prefix: &[u8], size_bytes: u64) -> Option<AudioMetadata> {
+    let audio_start = mp3::id3v2_len(prefix);
+    let (header, frame) = mp3::frames(&prefix[audio_start..]).next()?;
+    let audio_bytes = size_bytes.saturating_sub(audio_start as u64);
+    let frame_count = match mp3::xing_frame_count(frame, &header) {
+        Some(frames) => u64::from(frames),
+        None => audio_bytes / frame.len() as u64,
+    };
+    Some(summarize(&header, frame_count, audio_bytes, size_bytes))
+}
+
+/// Metadata for `size_bytes` of [`PCM_SAMPLE_RATE`] PCM, after a header of
+/// `header_len` bytes (0 for raw PCM, [`WAV_HEADER_LEN`] for WAV).
+pub fn pcm_metadata(size_bytes: u64, header_len: u64) -> AudioMetadata {
+    let frame_count = size_bytes.saturating_sub(header_len) / 2;
+    AudioMetadata {
+        duration_secs: frame_count as f64 / f64::from(PCM_SAMPLE_RATE),
+        size_bytes,
+        bitrate_kbps: PCM_SAMPLE_RATE * 16 / 1000,
+        sample_rate: PCM_SAMPLE_RATE,
+        channels: 1,
+        frame_count,
+    }
+}
+
+fn summarize(
+    header: &FrameHeader,
+    frame_count: u64,
+    audio_bytes: u64,
+    size_bytes: u64,
+) -> AudioMetadata {
+    let samples = frame_count * u64::from(header.samples_per_frame);
+    let duration_secs = samples as f64 / f64::from(header.sample_rate);
+    let bitrate_kbps = if duration_secs > 0.0 {
+        (audio_bytes as f64 * 8.0 / duration_secs / 1000.0).round() as u32
+    } else {
+        header.bitrate_kbps
+    };
+
+    AudioMetadata {
+        duration_secs,
+        size_bytes,
+        bitrate_kbps,
+        sample_rate: header.sample_rate,
+        channels: header.channels,
+        frame_count,
+    }
+}
+
+/// Like `read_exact`, but a short file is not an error.
+fn read_up_to(file: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MPEG-1 Layer III, 32 kbps, 48 kHz, mono: 96-byte, 24 ms frames.
+    fn frames(count: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        for _ in 0..count {
+            data.extend_from_slice(&[0xFF, 0xFB, 0x14, 0xC0]);
+            data.resize(data.len() + 92, 0);
+        }
+        data
+    }
+
+    #[test]
+    fn computes_duration_from_frame_headers() {
+        let meta = mp3_metadata(&frames(250)).unwrap();
+        assert_eq!(meta.frame_count, 250);
+        assert!((meta.duration_secs - 6.0).abs() < 1e-9);
+        assert_eq!(meta.bitrate_kbps, 32);
+        assert_eq!(meta.sample_rate, 48_000);
+        assert_eq!(meta.channels, 1);
+        assert_eq!(meta.size_bytes, 250 * 96);
+    }
+
+    #[test]
+    fn estimates_duration_from_a_prefix() {
+        let data = frames(250);
+        let meta = mp3_metadata_from_prefix(&data[..1024], data.len() as u64).unwrap();
+        assert_eq!(meta.frame_count, 250);
+        assert!((meta.duration_secs - 6.0).abs() < 1e-9);
+        assert_eq!(meta.size_bytes, 250 * 96);
+        assert_eq!(mp3_metadata_from_prefix(b"RIFF", 4), None);
+    }
+
+    #[test]
+    fn computes_pcm_duration_from_size() {
+        let meta = pcm_metadata(2 * 48_000, 0);
+        assert_eq!(meta.frame_count, 48_000);
+        assert!((meta.duration_secs - 2.0).abs() < 1e-9);
+        assert_eq!(meta.bitrate_kbps, 384);
+        let wav = pcm_metadata(WAV_HEADER_LEN + 48_000, WAV_HEADER_LEN);
+        assert!((wav.duration_secs - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn non_mp3_has_no_metadata() {
+        assert_eq!(mp3_metadata(b"RIFF....WAVEfmt "), None);
+    }
+}
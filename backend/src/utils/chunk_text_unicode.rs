@@ -2,29 +2,189 @@ use unicode_segmentation::UnicodeSegmentation;
 
 /// Splits `text` into chunks of up to `max_chars` *graphemes* each,
 /// ensuring you never break a Unicode character in half.
+///
+/// Chunks end at the most natural boundary that fits: paragraph breaks first,
+/// then sentence boundaries, then word boundaries. Only a single word longer
+/// than `max_chars` is cut between graphemes. Leading and trailing whitespace
+/// is trimmed from every chunk and empty chunks are dropped.
 pub fn chunk_text_unicode(text: &str, max_chars: usize) -> Vec<String> {
-    let mut results = Vec::new();
-    let mut current_chunk = String::with_capacity(max_chars);
-    let mut current_count = 0;
-
-    // Iterate over graphemes (what we usually call "characters")
-    for grapheme in text.graphemes(true) {
-        // If adding another grapheme would exceed `max_chars`,
-        // push the current chunk and start a fresh one.
-        if current_count >= max_chars {
-            results.push(current_chunk);
-            current_chunk = String::with_capacity(max_chars);
-            current_count = 0;
+    let mut packer = Packer {
+        max_chars: max_chars.max(1),
+        results: Vec::new(),
+        current_chunk: String::with_capacity(max_chars),
+        current_count: 0,
+    };
+    packer.pack(text, Level::Paragraph);
+    packer.flush();
+    packer.results
+}
+
+/// Boundary levels, from coarsest to finest.
+#[derive(Clone, Copy)]
+enum Level {
+    Paragraph,
+    Sentence,
+    Word,
+    Grapheme,
+}
+
+impl Level {
+    fn finer(self) -> Option<Level> {
+        match self {
+            Level::Paragraph => Some(Level::Sentence),
+            Level::Sentence => Some(Level::Word),
+            Level::Word => Some(Level::Grapheme),
+            Level::Grapheme => None,
+        }
+    }
+
+    /// Split `text` into pieces that concatenate back to `text` exactly.
+    fn split(self, text: &str) -> Vec<&str> {
+        match self {
+            Level::Paragraph => paragraphs(text),
+            Level::Sentence => text.split_sentence_bounds().collect(),
+            Level::Word => text.split_word_bounds().collect(),
+            Level::Grapheme => text.graphemes(true).collect(),
+        }
+    }
+}
+
+struct Packer {
+    max_chars: usize,
+    results: Vec<String>,
+    current_chunk: String,
+    current_count: usize,
+}
+
+impl Packer {
+    /// Greedily append the pieces of `text` at `level` to the current chunk,
+    /// descending to a finer level for any piece that can't fit on its own.
+    fn pack(&mut self, text: &str, level: Level) {
+        for piece in level.split(text) {
+            let count = piece.graphemes(true).count();
+
+            if self.current_count + count <= self.max_chars {
+                self.current_chunk.push_str(piece);
+                self.current_count += count;
+                continue;
+            }
+
+            // The piece doesn't fit: close the current chunk first.
+            self.flush();
+
+            match level.finer() {
+                Some(finer) if count > self.max_chars => self.pack(piece, finer),
+                _ => {
+                    self.current_chunk.push_str(piece);
+                    self.current_count = count;
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        let trimmed = self.current_chunk.trim();
+        if !trimmed.is_empty() {
+            self.results.push(trimmed.to_string());
         }
+        self.current_chunk.clear();
+        self.current_count = 0;
+    }
+}
+
+/// Split on blank lines, keeping each separator attached to the paragraph
+/// before it.
+fn paragraphs(text: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut pos = 0;
+    let mut after_blank = false;
+
+    for line in text.split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        if !blank && after_blank {
+            out.push(&text[start..pos]);
+            start = pos;
+            after_blank = false;
+        }
+        if blank && pos > start {
+            after_blank = true;
+        }
+        pos += line.len();
+    }
 
-        current_chunk.push_str(grapheme);
-        current_count += 1;
+    if start < text.len() {
+        out.push(&text[start..]);
     }
+    out
+}
 
-    // Push the final chunk if there's any leftover
-    if !current_chunk.is_empty() {
-        results.push(current_chunk);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graphemes(s: &str) -> usize {
+        s.graphemes(true).count()
     }
 
-    results
+    #[test]
+    fn short_text_is_a_single_chunk() {
+        assert_eq!(
+            chunk_text_unicode("  Hello there.  ", 100),
+            vec!["Hello there."]
+        );
+        assert!(chunk_text_unicode("   ", 100).is_empty());
+    }
+
+    #[test]
+    fn prefers_paragraph_breaks() {
+        let text = "First paragraph. Still first.\n\nSecond paragraph here.";
+        let chunks = chunk_text_unicode(text, 35);
+        assert_eq!(
+            chunks,
+            vec!["First paragraph. Still first.", "Second paragraph here."]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_sentences_then_words() {
+        let text = "One two three. Four five six. Seven eight nine.";
+        assert_eq!(
+            chunk_text_unicode(text, 30),
+            vec!["One two three. Four five six.", "Seven eight nine."]
+        );
+        assert_eq!(
+            chunk_text_unicode("alpha beta gamma delta", 11),
+            vec!["alpha beta", "gamma delta"]
+        );
+    }
+
+    #[test]
+    fn cuts_pathological_words_between_graphemes() {
+        let family = "👩‍👩‍👧‍👦";
+        let text = "ab".repeat(10) + " " + family + family;
+        let chunks = chunk_text_unicode(&text, 8);
+        assert_eq!(
+            chunks,
+            vec![
+                "abababab".to_string(),
+                "abababab".to_string(),
+                format!("abab {family}{family}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn never_exceeds_max_and_keeps_all_words() {
+        let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(40)
+            + "\n\n"
+            + &"Sed do eiusmod tempor incididunt ut labore. ".repeat(40);
+        let chunks = chunk_text_unicode(&text, 200);
+        assert!(chunks.iter().all(|c| graphemes(c) <= 200));
+
+        let original: Vec<&str> = text.split_whitespace().collect();
+        let rejoined = chunks.join(" ");
+        let rejoined: Vec<&str> = rejoined.split_whitespace().collect();
+        assert_eq!(original, rejoined);
+    }
 }
@@ -1,29 +1,226 @@
-use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Write};
 
-/// Naive MP3 concatenation:
-/// Copies the raw bytes of each input file into the output file in order,
-/// without decoding or re-encoding.
+use crate::utils::mp3::{self, FrameHeader};
+
+/// Tag, flags, frame count, byte count and 100-entry seek table.
+const XING_PAYLOAD_LEN: usize = 4 + 4 + 4 + 4 + 100;
+/// Frames, bytes and TOC fields are present.
+const XING_FLAGS: u32 = 0x0007;
+
+/// Frame-accurate MP3 concatenation:
+/// Walks the MPEG frames of each input file in order and writes only the audio
+/// frames, dropping ID3v2/ID3v1 tags and any Xing/Info/VBRI header frame each
+/// chunk carries. A single new Xing (VBR) or Info (CBR) frame with the total
+/// frame count, byte count and a seek table is written at the start, so players
+/// report the right duration and can seek.
 ///
+/// Every input must share the same MPEG version, layer and sample rate.
 /// Returns an `std::io::Result<()>` indicating success/failure.
-///
-/// # Caveat
-/// This doesn't validate MP3 frames or remove ID3 tags.
-/// The resulting file may have playback issues if each chunk
-/// is a fully self-contained MP3 with its own headers.
 pub fn concat_mp3(input_files: &[&str], output_file: &str) -> std::io::Result<()> {
-    // Create or overwrite the output file
-    let mut out = BufWriter::new(File::create(output_file)?);
+    // 1) First pass: find every audio frame so the header can be built.
+    let mut first: Option<(FrameHeader, [u8; 4])> = None;
+    let mut frame_offsets: Vec<usize> = Vec::new();
+    let mut audio_len = 0;
+    let mut vbr = false;
 
     for &mp3_path in input_files {
-        // Read each chunk as raw bytes
-        let mut f = BufReader::new(File::open(mp3_path)?);
+        let data = fs::read(mp3_path)?;
+        let mut frames_in_file = 0;
 
-        // Copy everything into output
-        std::io::copy(&mut f, &mut out)?;
+        for (header, frame) in audio_frames(&data) {
+            match &first {
+                None => first = Some((header, frame[..4].try_into().unwrap())),
+                Some((f, _))
+                    if f.version != header.version
+                        || f.layer != header.layer
+                        || f.sample_rate != header.sample_rate =>
+                {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("{mp3_path} does not match the stream format of the first chunk"),
+                    ));
+                }
+                Some((f, _)) => vbr |= f.bitrate_kbps != header.bitrate_kbps,
+            }
+            frame_offsets.push(audio_len);
+            audio_len += frame.len();
+            frames_in_file += 1;
+        }
+
+        if frames_in_file == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("no MP3 frames found in {mp3_path}"),
+            ));
+        }
+    }
+
+    let Some((_, first_raw)) = first else {
+        return Err(Error::new(ErrorKind::InvalidInput, "no input files"));
+    };
+    let header_frame = xing_frame(first_raw, &frame_offsets, audio_len, vbr)?;
+
+    // 2) Second pass: write the header frame followed by the audio frames.
+    let mut out = BufWriter::new(File::create(output_file)?);
+    out.write_all(&header_frame)?;
+    for &mp3_path in input_files {
+        let data = fs::read(mp3_path)?;
+        for (_, frame) in audio_frames(&data) {
+            out.write_all(frame)?;
+        }
     }
 
     // Flush ensures all data is written
     out.flush()?;
     Ok(())
 }
+
+/// Naive concatenation:
+/// Copies the raw bytes of each input file into the output file in order.
+/// Only correct for self-framing formats without a file header, such as
+/// ADTS AAC or raw PCM.
+pub fn concat_raw(input_files: &[&str], output_file: &str) -> std::io::Result<()> {
+    // Create or overwrite the output file
+    let mut out = BufWriter::new(File::create(output_file)?);
+
+    for &path in input_files {
+        // Copy each chunk's bytes into output
+        let mut f = BufReader::new(File::open(path)?);
+        std::io::copy(&mut f, &mut out)?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+/// Audio frames of one file: tags stripped and VBR header frames skipped.
+fn audio_frames(data: &[u8]) -> impl Iterator<Item = (FrameHeader, &[u8])> {
+    mp3::frames(mp3::strip_tags(data))
+        .filter(|(header, frame)| !mp3::is_vbr_header_frame(frame, header))
+}
+
+/// Build a Xing/Info frame describing `frame_offsets.len()` frames totalling
+/// `audio_len` bytes. `first_raw` is the header of the first audio frame; the
+/// new frame copies it, dropping CRC and padding and raising the bitrate if
+/// needed to fit the tag.
+fn xing_frame(
+    first_raw: [u8; 4],
+    frame_offsets: &[usize],
+    audio_len: usize,
+    vbr: bool,
+) -> std::io::Result<Vec<u8>> {
+    let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+
+    let mut raw = first_raw;
+    raw[1] |= 0x01; // protection bit set: no CRC
+    raw[2] &= 0x0D; // keep sample rate and private bits, clear padding
+    let template = FrameHeader::parse(&[raw[0], raw[1], raw[2] | 0x10, raw[3]])
+        .ok_or_else(|| invalid("invalid first frame header"))?;
+    let bitrate_index = template
+        .bitrate_index_for_len(template.xing_offset() + XING_PAYLOAD_LEN)
+        .ok_or_else(|| invalid("no bitrate large enough for a Xing frame"))?;
+    raw[2] |= bitrate_index << 4;
+    let header = FrameHeader::parse(&raw).ok_or_else(|| invalid("invalid Xing frame header"))?;
+
+    let total_len = header.frame_len + audio_len;
+    let frame_count = u32::try_from(frame_offsets.len()).map_err(|_| invalid("too many frames"))?;
+    let total_bytes = u32::try_from(total_len).map_err(|_| invalid("output too large"))?;
+
+    let mut frame = vec![0u8; header.frame_len];
+    frame[..4].copy_from_slice(&raw);
+
+    let mut payload = Vec::with_capacity(XING_PAYLOAD_LEN);
+    payload.extend_from_slice(if vbr { b"Xing" } else { b"Info" });
+    payload.extend_from_slice(&XING_FLAGS.to_be_bytes());
+    payload.extend_from_slice(&frame_count.to_be_bytes());
+    payload.extend_from_slice(&total_bytes.to_be_bytes());
+    // TOC entry i: position of the frame at i% of the duration, in 1/256ths
+    // of the file.
+    for i in 0..100 {
+        let offset = header.frame_len + frame_offsets[i * frame_offsets.len() / 100];
+        payload.push((offset * 256 / total_len).min(255) as u8);
+    }
+
+    let start = header.xing_offset();
+    frame[start..start + payload.len()].copy_from_slice(&payload);
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MPEG-1 Layer III, 32 kbps, 48 kHz, mono: 96-byte frames.
+    const HEADER: [u8; 4] = [0xFF, 0xFB, 0x14, 0xC0];
+
+    fn frame(fill: u8) -> Vec<u8> {
+        let mut f = vec![fill; 96];
+        f[..4].copy_from_slice(&HEADER);
+        f
+    }
+
+    /// A chunk the way encoders emit it: ID3v2, Info frame, audio, ID3v1.
+    fn chunk(audio_frames: usize) -> Vec<u8> {
+        let mut data = b"ID3\x04\x00\x00\x00\x00\x00\x14".to_vec();
+        data.extend_from_slice(&[0u8; 20]);
+        let mut info = frame(0);
+        info[21..25].copy_from_slice(b"Info");
+        data.extend_from_slice(&info);
+        for i in 0..audio_frames {
+            data.extend_from_slice(&frame(i as u8 + 1));
+        }
+        data.extend_from_slice(b"TAG");
+        data.extend_from_slice(&[b' '; 125]);
+        data
+    }
+
+    #[test]
+    fn strips_tags_and_writes_single_xing_header() {
+        let dir = std::env::temp_dir();
+        let pid = std::process::id();
+        let a = dir.join(format!("concat-a-{pid}.mp3"));
+        let b = dir.join(format!("concat-b-{pid}.mp3"));
+        let out = dir.join(format!("concat-out-{pid}.mp3"));
+        fs::write(&a, chunk(3)).unwrap();
+        fs::write(&b, chunk(2)).unwrap();
+
+        let result = concat_mp3(
+            &[a.to_str().unwrap(), b.to_str().unwrap()],
+            out.to_str().unwrap(),
+        );
+        let data = fs::read(&out);
+        for p in [&a, &b, &out] {
+            let _ = fs::remove_file(p);
+        }
+        result.unwrap();
+        let data = data.unwrap();
+
+        assert!(!data.starts_with(b"ID3"));
+        assert!(!data.windows(3).any(|w| w == b"TAG"));
+
+        let frames: Vec<_> = mp3::frames(&data).collect();
+        assert_eq!(frames.len(), 6);
+        let (header, first) = frames[0];
+        assert!(mp3::is_vbr_header_frame(first, &header));
+        assert!(frames[1..]
+            .iter()
+            .all(|(h, f)| !mp3::is_vbr_header_frame(f, h)));
+
+        let tag = header.xing_offset();
+        assert_eq!(&first[tag..tag + 4], b"Info");
+        let count = u32::from_be_bytes(first[tag + 8..tag + 12].try_into().unwrap());
+        let bytes = u32::from_be_bytes(first[tag + 12..tag + 16].try_into().unwrap());
+        assert_eq!(count, 5);
+        assert_eq!(bytes as usize, data.len());
+    }
+
+    #[test]
+    fn rejects_input_without_frames() {
+        let path = std::env::temp_dir().join(format!("concat-empty-{}.mp3", std::process::id()));
+        fs::write(&path, b"not an mp3").unwrap();
+        let err = concat_mp3(&[path.to_str().unwrap()], "/dev/null").unwrap_err();
+        let _ = fs::remove_file(&path);
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
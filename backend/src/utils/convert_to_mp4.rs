@@ -1,12 +1,27 @@
+use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::error::AppError;
 
 /// Convert an MP3 file to MP4 using `ffmpeg`.
-/// Returns `Ok(())` on success, otherwise an error message.
+/// Returns `Ok(())` on success, otherwise an [`AppError::Media`].
 ///
 /// The function prints detailed progress messages to `stderr` so you can
 /// trace every step of the conversion pipeline.
-pub fn convert_to_mp4(input: &str, output: &str) -> Result<(), String> {
+pub fn convert_to_mp4(input: &str, output: &str) -> Result<(), AppError> {
+    convert_to_mp4_with_progress(input, output, |_| {})
+}
+
+/// Same as [`convert_to_mp4`], but calls `on_progress` with the encoded
+/// fraction (0.0 to 1.0) as ffmpeg reports it through `-progress`.
+pub fn convert_to_mp4_with_progress(
+    input: &str,
+    output: &str,
+    mut on_progress: impl FnMut(f64),
+) -> Result<(), AppError> {
     eprintln!("🔧 convert_to_mp4() called");
     eprintln!("  ▶ input  file : {}", input);
     eprintln!("  ▶ output file : {}", output);
@@ -21,18 +36,24 @@ pub fn convert_to_mp4(input: &str, output: &str) -> Result<(), String> {
     // Check that the overlay file exists before invoking ffmpeg.
     if !img.exists() {
         eprintln!("  ❌ overlay image not found");
-        return Err(format!("overlay image not found at {}", img.display()));
+        return Err(AppError::Media(format!(
+            "overlay image not found at {}",
+            img.display()
+        )));
     }
     eprintln!("  ✅ overlay image found");
 
     // Build the full ffmpeg command for debugging visibility.
     let ffmpeg_args = [
         "-y", // overwrite output without asking
+        "-nostats",
+        "-progress",
+        "pipe:1", // machine-readable key=value progress on stdout
         "-loop",
         "1", // loop the static image forever
         "-i",
         img.to_str()
-            .ok_or_else(|| "invalid overlay path".to_string())?, // image input
+            .ok_or_else(|| AppError::Media("invalid overlay path".to_string()))?, // image input
         "-i",
         input,       // audio input
         "-shortest", // stop when shortest input ends (the audio)
@@ -52,25 +73,109 @@ pub fn convert_to_mp4(input: &str, output: &str) -> Result<(), String> {
         eprintln!("      [{}] {}", i, arg);
     }
 
-    let status = Command::new("ffmpeg")
-        .args(&ffmpeg_args)
-        .status()
+    let mut child = Command::new("ffmpeg")
+        .args(ffmpeg_args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
         .map_err(|e| {
             eprintln!("  ❌ failed to spawn ffmpeg: {e}");
-            format!("failed to spawn ffmpeg: {e}")
+            AppError::Media(format!("failed to spawn ffmpeg: {e}"))
         })?;
 
+    // ffmpeg logs each input's duration on stderr; forward the log and pick
+    // the audio's duration out of it so progress can be expressed as a
+    // fraction. The looped image comes first and has a duration of its own.
+    let (duration_tx, duration_rx) = mpsc::channel();
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let stderr_thread = thread::spawn(move || {
+        let mut input = None;
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            eprintln!("      {line}");
+            if let Some(secs) = audio_duration(&mut input, &line) {
+                let _ = duration_tx.send(secs);
+            }
+        }
+    });
+
+    let mut duration_secs: Option<f64> = None;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+        if duration_secs.is_none() {
+            duration_secs = duration_rx.try_recv().ok();
+        }
+        if line == "progress=end" {
+            on_progress(1.0);
+        } else if let (Some(done), Some(total)) = (parse_ffmpeg_out_time(&line), duration_secs) {
+            if total > 0.0 {
+                on_progress((done / total).clamp(0.0, 1.0));
+            }
+        }
+    }
+
+    let status = child.wait().map_err(|e| {
+        eprintln!("  ❌ failed to wait for ffmpeg: {e}");
+        AppError::Media(format!("failed to wait for ffmpeg: {e}"))
+    })?;
+    let _ = stderr_thread.join();
+
     eprintln!("  ▶ ffmpeg exited with status: {}", status);
 
     if !status.success() {
         eprintln!("  ❌ ffmpeg reported failure");
-        return Err(format!("ffmpeg exited with status {status}"));
+        return Err(AppError::Media(format!(
+            "ffmpeg exited with status {status}"
+        )));
     }
 
     eprintln!("  ✅ conversion completed successfully");
     Ok(())
 }
 
+/// Index of the audio among the ffmpeg inputs; input #0 is the image.
+const AUDIO_INPUT: usize = 1;
+
+/// Feed one line of ffmpeg's log; returns the audio input's duration once
+/// its banner shows up. `input` tracks which `Input #N` section the log is in.
+fn audio_duration(input: &mut Option<usize>, line: &str) -> Option<f64> {
+    if let Some(index) = parse_ffmpeg_input(line) {
+        *input = Some(index);
+        return None;
+    }
+    if *input == Some(AUDIO_INPUT) {
+        parse_ffmpeg_duration(line)
+    } else {
+        None
+    }
+}
+
+/// Parse the index out of an `Input #N, <format>, from '<path>':` header.
+fn parse_ffmpeg_input(line: &str) -> Option<usize> {
+    let rest = line.strip_prefix("Input #")?;
+    rest.split(',').next()?.parse().ok()
+}
+
+/// Parse the `Duration: HH:MM:SS.ss` banner ffmpeg prints for each input.
+fn parse_ffmpeg_duration(line: &str) -> Option<f64> {
+    let rest = line.trim_start().strip_prefix("Duration: ")?;
+    let stamp = rest.split(',').next()?;
+    let mut parts = stamp.split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Parse an `out_time_us=` (or legacy `out_time_ms=`, also microseconds)
+/// progress line into seconds.
+fn parse_ffmpeg_out_time(line: &str) -> Option<f64> {
+    let value = line
+        .strip_prefix("out_time_us=")
+        .or_else(|| line.strip_prefix("out_time_ms="))?;
+    let micros: i64 = value.trim().parse().ok()?;
+    Some(micros.max(0) as f64 / 1_000_000.0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -80,6 +185,39 @@ mod tests {
 
     static LOCK: Mutex<()> = Mutex::new(());
 
+    #[test]
+    fn parses_ffmpeg_progress_lines() {
+        let banner = "  Duration: 00:01:02.50, start: 0.000000, bitrate: 32 kb/s";
+        assert_eq!(parse_ffmpeg_duration(banner), Some(62.5));
+        assert_eq!(parse_ffmpeg_duration("Duration: N/A, bitrate: N/A"), None);
+
+        assert_eq!(parse_ffmpeg_out_time("out_time_us=1500000"), Some(1.5));
+        assert_eq!(parse_ffmpeg_out_time("out_time_ms=2000000"), Some(2.0));
+        assert_eq!(parse_ffmpeg_out_time("out_time_us=N/A"), None);
+        assert_eq!(parse_ffmpeg_out_time("frame=10"), None);
+    }
+
+    #[test]
+    fn takes_the_duration_of_the_audio_input() {
+        let log = [
+            "Input #0, png_pipe, from 'assets/wma.png':",
+            "  Duration: 00:00:00.04, start: 0.000000, bitrate: N/A",
+            "  Stream #0:0: Video: png, rgba(pc), 1280x720",
+            "Input #1, mp3, from 'final.mp3':",
+            "  Metadata:",
+            "    encoder         : Lavf60.3.100",
+            "  Duration: 00:02:05.30, start: 0.025057, bitrate: 160 kb/s",
+            "  Stream #1:0: Audio: mp3, 24000 Hz, mono, fltp, 160 kb/s",
+            "Output #0, mp4, to 'final.mp4':",
+        ];
+        let mut input = None;
+        let durations: Vec<_> = log
+            .iter()
+            .filter_map(|line| audio_duration(&mut input, line))
+            .collect();
+        assert_eq!(durations, [125.3]);
+    }
+
     #[test]
     fn overlay_missing_returns_error() {
         let _guard = LOCK.lock().unwrap();
@@ -95,7 +233,7 @@ mod tests {
         fs::rename(&backup, &overlay).expect("restore overlay from backup");
 
         let err = result.expect_err("expected error when overlay missing");
-        assert!(err.contains("overlay image not found"));
+        assert!(err.to_string().contains("overlay image not found"));
     }
 
     #[test]
@@ -112,17 +250,14 @@ mod tests {
 
         fs::write(&input, b"dummy").unwrap();
 
-        let result = convert_to_mp4(
-            input.to_str().unwrap(),
-            output.to_str().unwrap(),
-        );
+        let result = convert_to_mp4(input.to_str().unwrap(), output.to_str().unwrap());
 
         let _ = fs::remove_file(&input);
         let _ = fs::remove_file(&output);
 
         let err = result.expect_err("expected failure as ffmpeg likely missing");
         assert!(
-            !err.contains("overlay image not found"),
+            !err.to_string().contains("overlay image not found"),
             "error should not be about missing overlay"
         );
     }
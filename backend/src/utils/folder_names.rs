@@ -0,0 +1,117 @@
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use std::fs;
+use std::path::Path;
+use tracing::{error, info};
+
+use crate::utils::ulid::Ulid;
+
+/// Folder names written before ULIDs: local time with minute resolution,
+/// e.g. "2025-04-03-14:03".
+pub const LEGACY_FOLDER_FORMAT: &str = "%Y-%m-%d-%H:%M";
+
+/// Creation time encoded in an output folder name, either a ULID or a
+/// legacy local timestamp.
+pub fn folder_created_at(dir_name: &str) -> Option<DateTime<Utc>> {
+    if let Ok(id) = dir_name.parse::<Ulid>() {
+        return id.datetime();
+    }
+    let naive = NaiveDateTime::parse_from_str(dir_name, LEGACY_FOLDER_FORMAT).ok()?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|local| local.with_timezone(&Utc))
+}
+
+/// Rename every legacy-named folder under `root/<user_id>/` to a ULID carrying
+/// the same creation time. Returns how many folders were renamed.
+///
+/// Safe to run on every start: ULID-named folders are left alone. A folder
+/// that can't be read or renamed is logged and skipped, so it doesn't hold
+/// back the others.
+pub fn migrate_legacy_folders(root: &Path) -> usize {
+    let mut renamed = 0;
+    let Ok(users) = fs::read_dir(root) else {
+        return 0;
+    };
+
+    for user_dir in users.flatten() {
+        if !user_dir.file_type().is_ok_and(|t| t.is_dir()) {
+            continue;
+        }
+        let entries = match fs::read_dir(user_dir.path()) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to read {}: {}", user_dir.path().display(), e);
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue; // skip non-UTF8
+            };
+            if !entry.file_type().is_ok_and(|t| t.is_dir()) || name.parse::<Ulid>().is_ok() {
+                continue;
+            }
+            let Some(created_at) = folder_created_at(&name) else {
+                continue;
+            };
+
+            let id = Ulid::from_timestamp_ms(created_at.timestamp_millis().max(0) as u64);
+            let target = user_dir.path().join(id.to_string());
+            match fs::rename(entry.path(), &target) {
+                Ok(()) => {
+                    info!("Migrated legacy folder {} to {}", name, id);
+                    renamed += 1;
+                }
+                Err(e) => error!(
+                    "Failed to migrate legacy folder {}: {}",
+                    entry.path().display(),
+                    e
+                ),
+            }
+        }
+    }
+
+    renamed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_legacy_folders_keeping_their_time() {
+        let root = std::env::temp_dir().join(format!("folder-names-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let legacy = root.join("alice").join("2025-04-03-14:03");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(legacy.join("final.mp3"), b"ID3").unwrap();
+        let current = Ulid::new().to_string();
+        fs::create_dir_all(root.join("alice").join(&current)).unwrap();
+        fs::create_dir_all(root.join("bob").join("notes")).unwrap();
+
+        assert_eq!(migrate_legacy_folders(&root), 1);
+        let migrated: Vec<String> = fs::read_dir(root.join("alice"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| *name != current)
+            .collect();
+        assert_eq!(migrated.len(), 1);
+        assert!(migrated[0].parse::<Ulid>().is_ok());
+        assert_eq!(
+            folder_created_at(&migrated[0]),
+            folder_created_at("2025-04-03-14:03")
+        );
+        assert!(root
+            .join("alice")
+            .join(&migrated[0])
+            .join("final.mp3")
+            .exists());
+        // Names that aren't timestamps stay as they are
+        assert!(root.join("bob").join("notes").exists());
+
+        // Nothing left to do on the next start
+        assert_eq!(migrate_legacy_folders(&root), 0);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
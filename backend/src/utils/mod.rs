@@ -1,3 +1,8 @@
+pub mod audio_metadata;
 pub mod chunk_text_unicode;
 pub mod concat_mp3;
 pub mod convert_to_mp4;
+pub mod folder_names;
+pub mod mp3;
+pub mod storage_path;
+pub mod ulid;
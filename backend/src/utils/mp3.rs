@@ -0,0 +1,230 @@
+/// MPEG audio version from the frame header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpegVersion {
+    Mpeg1,
+    Mpeg2,
+    Mpeg25,
+}
+
+/// Decoded 4-byte MPEG audio frame header.
+///
+/// Only what's needed to walk a file frame by frame, skip tags and recognise
+/// Xing/Info/VBRI header frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub version: MpegVersion,
+    /// 1, 2 or 3.
+    pub layer: u8,
+    pub has_crc: bool,
+    pub bitrate_kbps: u32,
+    pub sample_rate: u32,
+    pub padding: bool,
+    pub channels: u8,
+    /// Total frame length in bytes, header included.
+    pub frame_len: usize,
+    pub samples_per_frame: u32,
+}
+
+const BITRATES_V1: [[u32; 15]; 3] = [
+    [
+        0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+    ],
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+    ],
+    [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ],
+];
+const BITRATES_V2: [[u32; 15]; 2] = [
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+    ],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+fn bitrate_kbps(version: MpegVersion, layer: u8, index: usize) -> u32 {
+    match version {
+        MpegVersion::Mpeg1 => BITRATES_V1[layer as usize - 1][index],
+        _ => BITRATES_V2[if layer == 1 { 0 } else { 1 }][index],
+    }
+}
+
+fn frame_len(
+    version: MpegVersion,
+    layer: u8,
+    bitrate_kbps: u32,
+    sample_rate: u32,
+    padding: bool,
+) -> usize {
+    let bits_per_sec = bitrate_kbps as usize * 1000;
+    let sample_rate = sample_rate as usize;
+    match (layer, version) {
+        (1, _) => (12 * bits_per_sec / sample_rate + padding as usize) * 4,
+        (3, MpegVersion::Mpeg2 | MpegVersion::Mpeg25) => {
+            72 * bits_per_sec / sample_rate + padding as usize
+        }
+        _ => 144 * bits_per_sec / sample_rate + padding as usize,
+    }
+}
+
+impl FrameHeader {
+    /// Parse the header at the start of `bytes`. Free-format and reserved
+    /// values are rejected.
+    pub fn parse(bytes: &[u8]) -> Option<FrameHeader> {
+        let b = bytes.get(..4)?;
+        if b[0] != 0xFF || b[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+
+        let version = match (b[1] >> 3) & 0b11 {
+            0b00 => MpegVersion::Mpeg25,
+            0b10 => MpegVersion::Mpeg2,
+            0b11 => MpegVersion::Mpeg1,
+            _ => return None,
+        };
+        let layer = match (b[1] >> 1) & 0b11 {
+            0b01 => 3,
+            0b10 => 2,
+            0b11 => 1,
+            _ => return None,
+        };
+        let has_crc = b[1] & 0x01 == 0;
+
+        let bitrate_index = (b[2] >> 4) as usize;
+        if bitrate_index == 0 || bitrate_index == 15 {
+            return None;
+        }
+        let bitrate_kbps = bitrate_kbps(version, layer, bitrate_index);
+
+        let base_rate = match (b[2] >> 2) & 0b11 {
+            0 => 44_100,
+            1 => 48_000,
+            2 => 32_000,
+            _ => return None,
+        };
+        let sample_rate = match version {
+            MpegVersion::Mpeg1 => base_rate,
+            MpegVersion::Mpeg2 => base_rate / 2,
+            MpegVersion::Mpeg25 => base_rate / 4,
+        };
+        let padding = b[2] & 0x02 != 0;
+        let channels = if b[3] >> 6 == 0b11 { 1 } else { 2 };
+
+        let samples_per_frame = match (layer, version) {
+            (1, _) => 384,
+            (3, MpegVersion::Mpeg2 | MpegVersion::Mpeg25) => 576,
+            _ => 1152,
+        };
+
+        Some(FrameHeader {
+            version,
+            layer,
+            has_crc,
+            bitrate_kbps,
+            sample_rate,
+            padding,
+            channels,
+            frame_len: frame_len(version, layer, bitrate_kbps, sample_rate, padding),
+            samples_per_frame,
+        })
+    }
+
+    /// Size of the Layer III side information that follows the header.
+    pub fn side_info_len(&self) -> usize {
+        match (self.version, self.channels) {
+            (MpegVersion::Mpeg1, 1) => 17,
+            (MpegVersion::Mpeg1, _) => 32,
+            (_, 1) => 9,
+            _ => 17,
+        }
+    }
+
+    /// Offset of a Xing/Info tag inside a frame with this header.
+    pub fn xing_offset(&self) -> usize {
+        4 + if self.has_crc { 2 } else { 0 } + self.side_info_len()
+    }
+
+    /// Smallest bitrate index whose unpadded frame is at least `min_len` bytes,
+    /// with every other header field unchanged.
+    pub fn bitrate_index_for_len(&self, min_len: usize) -> Option<u8> {
+        (1..15u8).find(|&i| {
+            let kbps = bitrate_kbps(self.version, self.layer, i as usize);
+            frame_len(self.version, self.layer, kbps, self.sample_rate, false) >= min_len
+        })
+    }
+}
+
+/// Length of a leading ID3v2 tag (header, body and optional footer), or 0.
+pub fn id3v2_len(data: &[u8]) -> usize {
+    if data.len() < 10 || &data[..3] != b"ID3" {
+        return 0;
+    }
+    // Tag size is a 28-bit "syncsafe" integer: 7 bits per byte.
+    let size = data[6..10]
+        .iter()
+        .fold(0usize, |acc, &b| (acc << 7) | (b & 0x7F) as usize);
+    let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+    (10 + size + footer).min(data.len())
+}
+
+/// `data` without a leading ID3v2 tag or a trailing 128-byte ID3v1 tag.
+pub fn strip_tags(data: &[u8]) -> &[u8] {
+    let mut audio = &data[id3v2_len(data)..];
+    if audio.len() >= 128 && &audio[audio.len() - 128..][..3] == b"TAG" {
+        audio = &audio[..audio.len() - 128];
+    }
+    audio
+}
+
+/// Whether `frame` is a Xing/Info or VBRI header frame rather than audio.
+pub fn is_vbr_header_frame(frame: &[u8], header: &FrameHeader) -> bool {
+    let xing = header.xing_offset();
+    let tag_at = |offset: usize| frame.get(offset..offset + 4);
+    matches!(tag_at(xing), Some(b"Xing") | Some(b"Info")) || tag_at(36) == Some(b"VBRI")
+}
+
+/// Frame count stored in a Xing/Info header frame, if it carries one.
+pub fn xing_frame_count(frame: &[u8], header: &FrameHeader) -> Option<u32> {
+    let tag = header.xing_offset();
+    if !matches!(frame.get(tag..tag + 4), Some(b"Xing") | Some(b"Info")) {
+        return None;
+    }
+    let flags = u32::from_be_bytes(frame.get(tag + 4..tag + 8)?.try_into().ok()?);
+    if flags & 0x1 == 0 {
+        return None;
+    }
+    Some(u32::from_be_bytes(
+        frame.get(tag + 8..tag + 12)?.try_into().ok()?,
+    ))
+}
+
+/// Iterator over `(header, frame bytes)` for every frame in `data`,
+/// resynchronising past any bytes that don't start a valid frame.
+pub fn frames(data: &[u8]) -> Frames<'_> {
+    Frames { data, pos: 0 }
+}
+
+pub struct Frames<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = (FrameHeader, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos + 4 <= self.data.len() {
+            if let Some(header) = FrameHeader::parse(&self.data[self.pos..]) {
+                let end = self.pos + header.frame_len;
+                if end <= self.data.len() {
+                    let frame = &self.data[self.pos..end];
+                    self.pos = end;
+                    return Some((header, frame));
+                }
+            }
+            self.pos += 1;
+        }
+        None
+    }
+}
@@ -0,0 +1,220 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Why a client-supplied folder or file name was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoragePathError {
+    /// Not a plain folder/file name (separators, dots, encoded bytes, ...).
+    Invalid(String),
+    /// Resolves (e.g. through a symlink) to somewhere outside the root.
+    Escapes,
+}
+
+impl fmt::Display for StoragePathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoragePathError::Invalid(name) => write!(f, "invalid name '{name}'"),
+            StoragePathError::Escapes => f.write_str("path escapes the storage root"),
+        }
+    }
+}
+
+/// A storage key such as `"<user_id>/<folder>/final.mp3"` whose every
+/// segment is a plain name (see [`validate_segment`]), so it can't point
+/// outside the folder it names. It can only be built by validating.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StoragePath(String);
+
+impl StoragePath {
+    /// Check every `/`-separated segment of `key`.
+    pub fn parse(key: &str) -> Result<Self, StoragePathError> {
+        key.split('/').try_for_each(validate_segment)?;
+        Ok(Self(key.to_string()))
+    }
+
+    /// The key with `name` appended as one more segment.
+    pub fn join(&self, name: &str) -> Result<Self, StoragePathError> {
+        validate_segment(name)?;
+        Ok(Self(format!("{}/{name}", self.0)))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split('/')
+    }
+}
+
+impl FromStr for StoragePath {
+    type Err = StoragePathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for StoragePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Resolve `key` to a path under `root`, which must already be canonical.
+///
+/// The deepest part of the path that already exists is canonicalized
+/// (following symlinks) and must still be under `root`. Missing trailing
+/// parts, e.g. a file about to be written, are returned as a plain join.
+pub fn resolve_key(root: &Path, key: &StoragePath) -> Result<PathBuf, StoragePathError> {
+    let mut path = root.to_path_buf();
+    path.extend(key.segments());
+
+    let mut existing = path.as_path();
+    while existing.symlink_metadata().is_err() {
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => return Err(StoragePathError::Escapes),
+        }
+    }
+    // A dangling symlink can't be canonicalized; refuse rather than write
+    // through it.
+    match existing.canonicalize() {
+        Ok(real) if real.starts_with(root) => Ok(path),
+        _ => Err(StoragePathError::Escapes),
+    }
+}
+
+/// Accept only plain names: ASCII letters, digits, `-`, `_`, `:` and
+/// non-leading dots. That covers ULIDs, legacy timestamp folders and our
+/// file names while ruling out separators, `.`/`..` and percent-encoding.
+pub fn validate_segment(name: &str) -> Result<(), StoragePathError> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.');
+    if name.is_empty() || name.starts_with('.') || !name.chars().all(allowed) {
+        return Err(StoragePathError::Invalid(name.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// `<tmp>/storage-path-<pid>-<tag>/{root/public/ok, outside}`, with the
+    /// canonical `root`.
+    fn fixture(tag: &str) -> (PathBuf, PathBuf) {
+        let base = std::env::temp_dir().join(format!("storage-path-{}-{tag}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let root = base.join("root");
+        fs::create_dir_all(root.join("public").join("ok")).unwrap();
+        fs::create_dir_all(base.join("outside")).unwrap();
+        fs::write(base.join("outside").join("secret.mp3"), b"x").unwrap();
+        (base, root.canonicalize().unwrap())
+    }
+
+    fn resolve(root: &Path, key: &str) -> Result<PathBuf, StoragePathError> {
+        resolve_key(root, &StoragePath::parse(key)?)
+    }
+
+    #[test]
+    fn resolves_plain_keys() {
+        let (base, root) = fixture("plain");
+        assert_eq!(
+            resolve(&root, "public/ok/final.mp3").unwrap(),
+            root.join("public/ok/final.mp3")
+        );
+        // Not there yet, e.g. a new output folder
+        assert_eq!(
+            resolve(&root, "public/new/final.mp3").unwrap(),
+            root.join("public/new/final.mp3")
+        );
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn rejects_dot_segments_and_separators() {
+        let (base, root) = fixture("dots");
+        for name in [
+            "",
+            ".",
+            "..",
+            "../outside",
+            "ok/..",
+            "ok\\..",
+            "/etc",
+            ".hidden",
+        ] {
+            assert!(
+                matches!(
+                    resolve(&root, &format!("public/{name}")),
+                    Err(StoragePathError::Invalid(_))
+                ),
+                "{name:?} should be rejected"
+            );
+        }
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn rejects_encoded_separators() {
+        let (base, root) = fixture("encoded");
+        for name in ["%2e%2e", "..%2F..%2Foutside", "%2E%2E%5Coutside", "ok%00"] {
+            assert!(
+                matches!(
+                    resolve(&root, &format!("public/{name}")),
+                    Err(StoragePathError::Invalid(_))
+                ),
+                "{name:?} should be rejected"
+            );
+        }
+        assert!(matches!(
+            resolve(&root, "public/ok/..%2Fsecret.mp3"),
+            Err(StoragePathError::Invalid(_))
+        ));
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn joins_only_plain_names() {
+        let folder = StoragePath::parse("alice/01JR0000000000000000000000").unwrap();
+        assert_eq!(
+            folder.join("final.mp3").unwrap().as_str(),
+            "alice/01JR0000000000000000000000/final.mp3"
+        );
+        assert!(folder.join("../bob").is_err());
+        assert!(folder.join("").is_err());
+        assert!("alice//final.mp3".parse::<StoragePath>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_out_of_the_root() {
+        use std::os::unix::fs::symlink;
+
+        let (base, root) = fixture("symlink");
+        let outside = base.join("outside");
+        symlink(&outside, root.join("public").join("link")).unwrap();
+        symlink(outside.join("secret.mp3"), root.join("public/ok/final.mp3")).unwrap();
+        symlink(
+            outside.join("missing.mp3"),
+            root.join("public/ok/dangling.mp3"),
+        )
+        .unwrap();
+
+        for key in [
+            "public/link/secret.mp3",
+            "public/link/new.mp3",
+            "public/ok/final.mp3",
+            "public/ok/dangling.mp3",
+        ] {
+            assert_eq!(
+                resolve(&root, key).unwrap_err(),
+                StoragePathError::Escapes,
+                "{key:?} should be refused"
+            );
+        }
+        fs::remove_dir_all(base).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Crockford base32 alphabet (no I, L, O, U).
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const ENCODED_LEN: usize = 26;
+const RANDOM_BITS: u32 = 80;
+const RANDOM_MASK: u128 = (1 << RANDOM_BITS) - 1;
+
+/// Last ID handed out by `Ulid::new`, so IDs from the same millisecond still
+/// sort in creation order.
+static LAST: Mutex<u128> = Mutex::new(0);
+
+/// Universally Unique Lexicographically Sortable Identifier: a 48-bit
+/// millisecond timestamp followed by 80 random bits, written as 26 Crockford
+/// base32 characters. String order matches creation order, and the
+/// characters are safe in file names on every platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ulid(u128);
+
+impl Ulid {
+    /// A new ID for the current time, strictly greater than any previous one
+    /// from this process.
+    pub fn new() -> Self {
+        let candidate = Self::from_timestamp_ms(Utc::now().timestamp_millis().max(0) as u64);
+        let mut last = LAST.lock().unwrap();
+        // Same (or earlier, if the clock stepped back) millisecond: bump the
+        // random part of the previous ID instead.
+        let value = if candidate.0 >> RANDOM_BITS <= *last >> RANDOM_BITS {
+            *last + 1
+        } else {
+            candidate.0
+        };
+        *last = value;
+        Ulid(value)
+    }
+
+    /// An ID for the given Unix time in milliseconds with random low bits.
+    pub fn from_timestamp_ms(ms: u64) -> Self {
+        let random = rand::rng().random::<u128>() & RANDOM_MASK;
+        Ulid((u128::from(ms) << RANDOM_BITS) | random)
+    }
+
+    pub fn timestamp_ms(&self) -> u64 {
+        (self.0 >> RANDOM_BITS) as u64
+    }
+
+    pub fn datetime(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp_millis(self.timestamp_ms() as i64)
+    }
+}
+
+impl Default for Ulid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Ulid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buf = [0u8; ENCODED_LEN];
+        for (i, byte) in buf.iter_mut().enumerate() {
+            let shift = 5 * (ENCODED_LEN - 1 - i);
+            *byte = ALPHABET[((self.0 >> shift) & 0x1F) as usize];
+        }
+        f.write_str(std::str::from_utf8(&buf).expect("alphabet is ASCII"))
+    }
+}
+
+impl FromStr for Ulid {
+    type Err = String;
+
+    /// Parses the canonical upper-case form; lower case is accepted too.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != ENCODED_LEN {
+            return Err(format!("ULID must be {ENCODED_LEN} characters"));
+        }
+        // The first character only carries 3 bits (26 * 5 = 130 > 128).
+        if s.as_bytes()[0] > b'7' {
+            return Err("ULID is out of range".to_string());
+        }
+
+        let mut value: u128 = 0;
+        for c in s.bytes() {
+            let digit = ALPHABET
+                .iter()
+                .position(|&a| a == c.to_ascii_uppercase())
+                .ok_or_else(|| format!("invalid ULID character '{}'", c as char))?;
+            value = (value << 5) | digit as u128;
+        }
+        Ok(Ulid(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_string() {
+        let id = Ulid::new();
+        let s = id.to_string();
+        assert_eq!(s.len(), 26);
+        assert_eq!(s.parse::<Ulid>().unwrap(), id);
+        assert_eq!(s.to_lowercase().parse::<Ulid>().unwrap(), id);
+        assert!("not-a-ulid".parse::<Ulid>().is_err());
+        assert!("8ZZZZZZZZZZZZZZZZZZZZZZZZZ".parse::<Ulid>().is_err());
+    }
+
+    #[test]
+    fn ids_sort_in_creation_order() {
+        let ids: Vec<String> = (0..1000).map(|_| Ulid::new().to_string()).collect();
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert_eq!(ids, sorted);
+    }
+
+    #[test]
+    fn keeps_timestamp() {
+        let ms = 1_743_688_980_000; // 2025-04-03T14:03:00Z
+        let id = Ulid::from_timestamp_ms(ms);
+        assert_eq!(id.timestamp_ms(), ms);
+        assert_eq!(
+            id.datetime().unwrap().to_rfc3339(),
+            "2025-04-03T14:03:00+00:00"
+        );
+    }
+}
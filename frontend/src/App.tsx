@@ -1,48 +1,136 @@
-import { useState } from "react";
+import { useEffect, useState } from "react";
+import { mutate } from "swr";
+import { SignedIn, SignedOut, SignInButton, UserButton } from "@clerk/clerk-react";
+import { useAuthFetch } from "./lib/api";
 import UsersTable from "./components/users-table";
 import FilesTable from "./components/files-table";
 import { Box, Button, Flex, Heading, Text, TextArea } from "@radix-ui/themes";
 
+// Snapshot returned by GET /api/jobs/{id}
+interface JobSchema {
+  id: string;
+  state: "queued" | "synthesizing" | "merging" | "encoding" | "done" | "failed";
+  chunk?: number;
+  total?: number;
+  progress?: number;
+  result_url?: string;
+  error?: string;
+  code?: string;
+}
+
+const POLL_INTERVAL_MS = 1000;
+
+function isFinished(job: JobSchema) {
+  return job.state === "done" || job.state === "failed";
+}
+
+function describeJob(job: JobSchema) {
+  switch (job.state) {
+    case "queued":
+      return "Waiting in the queue...";
+    case "synthesizing":
+      return `Synthesizing chunk ${job.chunk} of ${job.total}...`;
+    case "merging":
+      return "Merging audio...";
+    case "encoding":
+      return `Encoding video (${Math.round((job.progress ?? 0) * 100)}%)...`;
+    case "done":
+      return "Done.";
+    case "failed":
+      return `Failed: ${job.error}`;
+  }
+}
+
 function App() {
   const [text, setText] = useState("");
+  const [job, setJob] = useState<JobSchema | null>(null);
+  const [error, setError] = useState<string | null>(null);
+  const authFetch = useAuthFetch();
 
-  function handleSubmit(e: React.FormEvent<HTMLFormElement>) {
+  async function handleSubmit(e: React.FormEvent<HTMLFormElement>) {
     e.preventDefault();
+    setError(null);
 
-    // Fire-and-forget: just call fetch with no awaits or .then handlers
-    fetch("/api/speech", {
+    // 1) Queue the job; the backend answers 202 with its id
+    const res = await authFetch("/api/speech", {
       method: "POST",
       headers: { "Content-Type": "application/json" },
       body: JSON.stringify({ input: text }),
     });
-
-    // (Optional) You can clear the text or otherwise give user feedback immediately:
+    const body = await res.json();
+    if (!res.ok) {
+      setError(body.error ?? `Request failed (${res.status})`);
+      return;
+    }
+    setJob({ id: body.job_id, state: "queued" });
     setText("");
   }
 
+  // 2) Poll the job until it is done or has failed
+  useEffect(() => {
+    if (!job || isFinished(job)) return;
+    const timer = setTimeout(async () => {
+      const res = await authFetch(`/api/jobs/${job.id}`);
+      const body = await res.json();
+      if (!res.ok) {
+        setError(body.error ?? `Request failed (${res.status})`);
+        return;
+      }
+      setJob(body);
+      // 3) Show the new file in the table as soon as it exists
+      if (body.state === "done") mutate("/api/files");
+    }, POLL_INTERVAL_MS);
+    return () => clearTimeout(timer);
+  }, [job, authFetch]);
+
+  const busy = job !== null && !isFinished(job) && error === null;
+
   return (
     <Flex direction="column" align="center" gap="6" p="6" className="min-h-screen">
       <Box maxWidth="600px" width="100%">
-        <Heading as="h1" size="6" mb="4">
-          Text-to-Speech Demo (Fire & Forget)
-        </Heading>
-        <form onSubmit={handleSubmit}>
-          <Flex direction="column" gap="3">
-            <Text as="label" htmlFor="tts-input" weight="medium">
-              Text to Speak (no response):
-            </Text>
-            <TextArea
-              id="tts-input"
-              rows={5}
-              value={text}
-              onChange={(e) => setText(e.target.value)}
-            />
-            <Button type="submit">Send Request</Button>
-          </Flex>
-        </form>
+        <Flex justify="between" align="center" mb="4">
+          <Heading as="h1" size="6">
+            Text-to-Speech Demo
+          </Heading>
+          <SignedIn>
+            <UserButton />
+          </SignedIn>
+        </Flex>
+        <SignedOut>
+          <SignInButton>
+            <Button>Sign in to generate speech</Button>
+          </SignInButton>
+        </SignedOut>
+        <SignedIn>
+          <form onSubmit={handleSubmit}>
+            <Flex direction="column" gap="3">
+              <Text as="label" htmlFor="tts-input" weight="medium">
+                Text to Speak:
+              </Text>
+              <TextArea
+                id="tts-input"
+                rows={5}
+                value={text}
+                onChange={(e) => setText(e.target.value)}
+              />
+              <Button type="submit" disabled={busy}>
+                Send Request
+              </Button>
+              {job && <Text>{describeJob(job)}</Text>}
+              {job?.state === "done" && job.result_url && (
+                <audio controls src={job.result_url}>
+                  Your browser does not support the audio element.
+                </audio>
+              )}
+              {error && <Text color="red">{error}</Text>}
+            </Flex>
+          </form>
+        </SignedIn>
       </Box>
-      <UsersTable />
-      <FilesTable />
+      <SignedIn>
+        <UsersTable />
+        <FilesTable />
+      </SignedIn>
     </Flex>
   );
 }
@@ -11,17 +11,33 @@ import {
 } from "@/components/ui/table";
 import { Loader as LucideLoader } from "lucide-react";
 import useSWR from "swr";
+import { useAuthFetch } from "@/lib/api";
 
 // Data shape returned by GET /api/speech/files
 export interface FinalFileSchema {
   timestamp: string;
   file_path: string;
   dir_name: string;
+  // final.<ext> and its MIME type, in the format the audio was requested in
+  file_name: string;
+  content_type: string;
+  // Present when the backend knows the audio's length
+  duration_secs?: number;
+  size_bytes?: number;
+}
+
+function formatDuration(secs?: number) {
+  if (secs === undefined) return "—";
+  const total = Math.round(secs);
+  const minutes = Math.floor(total / 60);
+  const seconds = String(total % 60).padStart(2, "0");
+  return `${minutes}:${seconds}`;
 }
 
 export default function FilesTable() {
+  const authFetch = useAuthFetch();
   const { isLoading, data } = useSWR("/api/files", (url) =>
-    fetch(url).then((res) => res.json())
+    authFetch(url).then((res) => res.json())
   );
 
   // 2) Handle loading / error states
@@ -50,6 +66,7 @@ export default function FilesTable() {
         <TableHeader>
           <TableRow>
             <TableHead>Timestamp</TableHead>
+            <TableHead>Length</TableHead>
             <TableHead>Audio</TableHead>
             <TableHead>File</TableHead>
             <TableHead>Video</TableHead>
@@ -59,9 +76,10 @@ export default function FilesTable() {
           {data.map((file: any, index: any) => (
             <TableRow key={index}>
               <TableCell>{file.timestamp}</TableCell>
+              <TableCell>{formatDuration(file.duration_secs)}</TableCell>
               <TableCell>
                 <audio controls>
-                  <source src={file.file_path} type="audio/mpeg" />
+                  <source src={file.file_path} type={file.content_type} />
                   Your browser does not support the audio element.
                 </audio>
               </TableCell>
@@ -72,7 +90,7 @@ export default function FilesTable() {
                   rel="noopener noreferrer"
                   className="text-blue-600 underline"
                 >
-                  final.mp3
+                  {file.file_name}
                 </a>
               </TableCell>
             </TableRow>
@@ -9,6 +9,7 @@ import {
 import { Avatar, AvatarFallback, AvatarImage } from "@/components/ui/avatar";
 import { Loader } from "lucide-react";
 import useSWR from "swr";
+import { useAuthFetch } from "@/lib/api";
 
 export interface UserSchema {
   first_name: string;
@@ -19,7 +20,8 @@ export interface UserSchema {
 }
 
 export default function UsersTable() {
-  const { isLoading, data } = useSWR("/api/users", (url) => fetch(url).then(res => res.json()));
+  const authFetch = useAuthFetch();
+  const { isLoading, data } = useSWR("/api/users", (url) => authFetch(url).then(res => res.json()));
 
   return (
     <>
@@ -0,0 +1,18 @@
+import { useCallback } from "react";
+import { useAuth } from "@clerk/clerk-react";
+
+// fetch() that sends the Clerk session token as a bearer token, so /api
+// requests are authenticated whether or not the session cookie is sent
+export function useAuthFetch() {
+  const { getToken } = useAuth();
+
+  return useCallback(
+    async (url: string, init: RequestInit = {}) => {
+      const token = await getToken();
+      const headers = new Headers(init.headers);
+      if (token) headers.set("Authorization", `Bearer ${token}`);
+      return fetch(url, { ...init, headers });
+    },
+    [getToken]
+  );
+}
@@ -4,12 +4,15 @@ import App from './App.tsx'
 import './index.css'
 import '@radix-ui/themes/styles.css'
 import { Theme } from '@radix-ui/themes'
+import { ClerkProvider } from '@clerk/clerk-react'
 
 
 ReactDOM.createRoot(document.getElementById('root')!).render(
   <React.StrictMode>
-    <Theme>
-      <App />
-    </Theme>
+    <ClerkProvider publishableKey={import.meta.env.VITE_CLERK_PUBLISHABLE_KEY}>
+      <Theme>
+        <App />
+      </Theme>
+    </ClerkProvider>
   </React.StrictMode>,
 )